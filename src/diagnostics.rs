@@ -0,0 +1,43 @@
+use std::fmt::Display;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// How much diagnostic output is printed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// Errors only
+    Quiet = 0,
+    /// Errors and warnings
+    Normal = 1,
+    /// Errors, warnings and details about loaded files
+    Verbose = 2,
+}
+
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+
+/// Set the verbosity for the rest of the process
+pub fn set_verbosity(verbosity: Verbosity) {
+    VERBOSITY.store(verbosity as u8, Ordering::Relaxed);
+}
+
+fn enabled(verbosity: Verbosity) -> bool {
+    VERBOSITY.load(Ordering::Relaxed) >= verbosity as u8
+}
+
+/// Print an error to stderr, also with `--quiet`
+pub fn error(message: impl Display) {
+    eprintln!("Error: {}", message);
+}
+
+/// Print a warning to stderr unless `--quiet` is given
+pub fn warn(message: impl Display) {
+    if enabled(Verbosity::Normal) {
+        eprintln!("Warning: {}", message);
+    }
+}
+
+/// Print a detail to stderr if `--verbose` is given
+pub fn verbose(message: impl Display) {
+    if enabled(Verbosity::Verbose) {
+        eprintln!("{}", message);
+    }
+}
@@ -0,0 +1,358 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use clipboard::{ClipboardContext, ClipboardProvider};
+
+use crate::error::{Result, YkError};
+use crate::manifest;
+use crate::picker::PickerBackend;
+use crate::placeholder;
+use crate::shell::Shell;
+use crate::sources::{self, PluginFile, Scope};
+use crate::{
+    generate_snippets, get_config_file, load_config, parse_snippet_file, qualified_id,
+    CommandSnippet, YkConfig,
+};
+
+/// Severity of a check result
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Ok,
+    Warning,
+    Error,
+}
+
+/// Collects and prints check results
+#[derive(Default)]
+struct Report {
+    warnings: usize,
+    errors: usize,
+}
+
+impl Report {
+    fn section(&self, name: &str) {
+        println!();
+        println!("{}", name);
+    }
+
+    fn check(&mut self, status: Status, message: impl AsRef<str>) {
+        let label = match status {
+            Status::Ok => "ok",
+            Status::Warning => {
+                self.warnings += 1;
+                "warning"
+            }
+            Status::Error => {
+                self.errors += 1;
+                "error"
+            }
+        };
+        println!("  {:<8} {}", label, message.as_ref());
+    }
+}
+
+/// Snippet file together with the plugin it belongs to
+struct SnippetSource {
+    plugin_name: String,
+    scope: Scope,
+    commands: BTreeMap<String, CommandSnippet>,
+}
+
+/// Check that a configured program can be found
+fn check_program(report: &mut Report, name: &str, program: &Path, missing: Status) {
+    match which::which(program) {
+        Ok(path) => report.check(Status::Ok, format!("{}: {}", name, path.display())),
+        Err(_) if missing == Status::Ok => report.check(
+            missing,
+            format!("{}: '{}' not found, not required", name, program.display()),
+        ),
+        Err(_) => report.check(
+            missing,
+            format!("{}: '{}' not found", name, program.display()),
+        ),
+    }
+}
+
+/// Whether a file may be executed directly
+fn is_executable(path: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        fs::metadata(path)
+            .map(|metadata| metadata.permissions().mode() & 0o111 != 0)
+            .unwrap_or(false)
+    }
+    #[cfg(not(unix))]
+    {
+        path.is_file()
+    }
+}
+
+/// Parse a snippet file, or run a generator, and report its commands
+fn check_snippet_file(
+    report: &mut Report,
+    sources: &mut Vec<SnippetSource>,
+    config: &YkConfig,
+    plugin_file: &PluginFile,
+) {
+    let plugin_name = &plugin_file.name;
+    let config_file = &plugin_file.config_file;
+
+    let manifest = manifest::load(plugin_file);
+    match manifest {
+        Ok(Some(ref manifest)) => {
+            if !config.is_plugin_enabled(plugin_name, Some(manifest)) {
+                report.check(
+                    Status::Ok,
+                    format!("{}: disabled by its manifest", plugin_name),
+                );
+                return;
+            }
+            if let Some(reason) = manifest.unmet_requirement() {
+                report.check(
+                    Status::Warning,
+                    format!("{}: commands are disabled, {}", plugin_name, reason),
+                );
+            }
+        }
+        Ok(None) => {}
+        Err(ref e) => report.check(
+            Status::Error,
+            format!("{}: invalid manifest: {}", plugin_name, e),
+        ),
+    }
+
+    let file = if plugin_file.generator {
+        generate_snippets(config, plugin_file, manifest.ok().flatten().as_ref())
+    } else {
+        parse_snippet_file(config_file)
+    };
+    match file {
+        Ok(file) => {
+            for (name, e) in &file.errors {
+                report.check(
+                    Status::Error,
+                    format!("{}: {}", qualified_id(plugin_name, name), e),
+                );
+            }
+            if file.errors.is_empty() {
+                report.check(
+                    Status::Ok,
+                    format!(
+                        "{}: {} ({} commands)",
+                        plugin_name,
+                        config_file.display(),
+                        file.commands.len()
+                    ),
+                );
+            }
+            sources.push(SnippetSource {
+                plugin_name: plugin_name.clone(),
+                scope: plugin_file.scope,
+                commands: file.commands.into_iter().collect(),
+            });
+        }
+        Err(e) => report.check(Status::Error, format!("{}: {}", plugin_name, e)),
+    }
+}
+
+/// Check the commands of all parsed snippet files
+fn check_commands(report: &mut Report, config: &YkConfig, sources: &[SnippetSource]) {
+    let mut names: BTreeMap<&str, Vec<(&str, Scope)>> = BTreeMap::new();
+
+    for source in sources {
+        for (name, command) in &source.commands {
+            let id = qualified_id(&source.plugin_name, name);
+            names
+                .entry(name.as_str())
+                .or_default()
+                .push((source.plugin_name.as_str(), source.scope));
+
+            // Values of placeholders are only known when the command runs
+            let is_template = |path: &PathBuf| placeholder::is_template(&path.to_string_lossy());
+            let shell = || {
+                command
+                    .shell
+                    .as_ref()
+                    .or(config.shell.as_ref())
+                    .map(Shell::from_config)
+                    .unwrap_or_default()
+            };
+
+            // Scripts only need their interpreter
+            if command.script.is_some() {
+                let interpreter = command
+                    .interpreter
+                    .clone()
+                    .unwrap_or_else(|| shell().program);
+                if !is_template(&interpreter) && which::which(&interpreter).is_err() {
+                    report.check(
+                        Status::Error,
+                        format!("{}: interpreter '{}' not found", id, interpreter.display()),
+                    );
+                }
+                continue;
+            }
+
+            if let Some(ref executable) = command.executable {
+                if !is_template(executable) && which::which(executable).is_err() {
+                    report.check(
+                        Status::Error,
+                        format!("{}: executable '{}' not found", id, executable.display()),
+                    );
+                }
+            }
+
+            if let Some(ref entry_point) = command.entry_point {
+                if is_template(entry_point) {
+                    // Checked when the command runs
+                } else if !entry_point.is_file() {
+                    report.check(
+                        Status::Error,
+                        format!(
+                            "{}: entry point {} does not exist",
+                            id,
+                            entry_point.display()
+                        ),
+                    );
+                } else if command.executable.is_none() && !is_executable(entry_point) {
+                    // Entry points passed to an interpreter need no execute permission
+                    report.check(
+                        Status::Error,
+                        format!(
+                            "{}: entry point {} is not executable",
+                            id,
+                            entry_point.display()
+                        ),
+                    );
+                }
+            }
+
+            if command.if_shell {
+                let shell = shell();
+                if which::which(&shell.program).is_err() {
+                    report.check(
+                        Status::Error,
+                        format!("{}: shell '{}' not found", id, shell.program.display()),
+                    );
+                }
+            }
+        }
+    }
+
+    // The same name in several plugins must be qualified when run by name
+    for (name, mut plugins) in names {
+        // System commands are meant to be overridden by user and project commands
+        if plugins.iter().any(|(_, scope)| *scope != Scope::System) {
+            plugins.retain(|(_, scope)| *scope != Scope::System);
+        }
+        if plugins.len() > 1 {
+            let candidates: Vec<String> = plugins
+                .iter()
+                .map(|(plugin, _)| qualified_id(plugin, name))
+                .collect();
+            report.check(
+                Status::Warning,
+                format!(
+                    "'{}' is defined {} times: {}",
+                    name,
+                    plugins.len(),
+                    candidates.join(", ")
+                ),
+            );
+        }
+    }
+}
+
+/// Validate the configuration, external programs and all snippet files
+///
+/// Fails if any check reports an error, warnings only are printed.
+pub fn doctor() -> Result<()> {
+    let mut report = Report::default();
+
+    report.section("Configuration");
+    let config = match load_config() {
+        Ok(config) => {
+            report.check(
+                Status::Ok,
+                format!("config file: {}", get_config_file().display()),
+            );
+            config
+        }
+        Err(e) => {
+            report.check(Status::Error, e.to_string());
+            YkConfig::default()
+        }
+    };
+
+    report.section("Programs");
+    // Without fzf the builtin picker is used unless fzf is required explicitly
+    let fzf_missing = match config.picker {
+        PickerBackend::Fzf => Status::Error,
+        PickerBackend::Auto => Status::Warning,
+        PickerBackend::Builtin => Status::Ok,
+    };
+    check_program(&mut report, "fzf", &config.fzf_executable, fzf_missing);
+    check_program(&mut report, "editor", &config.editor, Status::Error);
+
+    report.section("Clipboard");
+    let clipboard_missing = if config.if_yank {
+        Status::Error
+    } else {
+        Status::Warning
+    };
+    match ClipboardProvider::new().and_then(|mut ctx: ClipboardContext| ctx.get_contents()) {
+        Ok(_) => report.check(Status::Ok, "clipboard is accessible"),
+        Err(e) => report.check(
+            clipboard_missing,
+            format!("clipboard is not accessible: {}", e),
+        ),
+    }
+
+    report.section("Snippet files");
+    let mut sources = Vec::new();
+    for plugin_file in sources::plugin_files(&config) {
+        let config_file = &plugin_file.config_file;
+        if !config.is_plugin_enabled(&plugin_file.name, None) {
+            report.check(Status::Ok, format!("{}: disabled", plugin_file.name));
+        } else if config_file.is_file() {
+            check_snippet_file(&mut report, &mut sources, &config, &plugin_file);
+        } else {
+            // The simple commands file is created by `yk init` and optional
+            let status = if plugin_file.required {
+                Status::Error
+            } else {
+                Status::Warning
+            };
+            report.check(
+                status,
+                format!(
+                    "{}: {} does not exist",
+                    plugin_file.name,
+                    config_file.display()
+                ),
+            );
+        }
+    }
+
+    report.section("Commands");
+    let problems_before = report.errors + report.warnings;
+    check_commands(&mut report, &config, &sources);
+    if report.errors + report.warnings == problems_before {
+        let count: usize = sources.iter().map(|source| source.commands.len()).sum();
+        report.check(Status::Ok, format!("{} commands checked", count));
+    }
+
+    println!();
+    println!("{} errors, {} warnings", report.errors, report.warnings);
+
+    if report.errors > 0 {
+        return Err(YkError::Config(format!(
+            "yk doctor found {} problems",
+            report.errors
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,125 @@
+use std::fs;
+use std::path::Path;
+
+use crate::error::YkError;
+
+/// Parse a double-quoted value, handling backslash escapes
+fn parse_double_quoted(value: &str) -> Option<String> {
+    let mut result = String::new();
+    let mut chars = value.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => return Some(result),
+            '\\' => match chars.next()? {
+                'n' => result.push('\n'),
+                'r' => result.push('\r'),
+                't' => result.push('\t'),
+                other => result.push(other),
+            },
+            _ => result.push(c),
+        }
+    }
+
+    None
+}
+
+/// Parse the value part of a `KEY=VALUE` line
+fn parse_value(value: &str) -> Option<String> {
+    let value = value.trim();
+
+    if let Some(rest) = value.strip_prefix('"') {
+        parse_double_quoted(rest)
+    } else if let Some(rest) = value.strip_prefix('\'') {
+        rest.find('\'').map(|end| rest[..end].to_string())
+    } else {
+        // Unquoted values end at an inline comment
+        let value = match value.find(" #") {
+            Some(index) => &value[..index],
+            None => value,
+        };
+        Some(value.trim_end().to_string())
+    }
+}
+
+/// Parse dotenv content into key value pairs, in file order
+pub fn parse(content: &str) -> Result<Vec<(String, String)>, String> {
+    let mut vars = Vec::new();
+
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| format!("line {}: expected KEY=VALUE", index + 1))?;
+
+        let key = key.trim();
+        if key.is_empty() || key.contains(char::is_whitespace) {
+            return Err(format!(
+                "line {}: invalid variable name '{}'",
+                index + 1,
+                key
+            ));
+        }
+
+        let value =
+            parse_value(value).ok_or_else(|| format!("line {}: unterminated quote", index + 1))?;
+        vars.push((key.to_string(), value));
+    }
+
+    Ok(vars)
+}
+
+/// Load a dotenv file
+pub fn load(path: &Path) -> Result<Vec<(String, String)>, YkError> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| YkError::Config(format!("Failed to read env file {:?}: {}", path, e)))?;
+    parse(&content).map_err(|e| YkError::Config(format!("Env file {:?} format error: {}", path, e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_reads_quoted_and_unquoted_values() {
+        let content = r#"
+# comment
+export TOKEN=abc
+NAME = "Jane \"J\" Doe\n"
+RAW='a $b \n'
+PORT=8080 # inline comment
+EMPTY=
+URL=http://host/#anchor
+"#;
+        assert_eq!(
+            parse(content).unwrap(),
+            [
+                ("TOKEN", "abc"),
+                ("NAME", "Jane \"J\" Doe\n"),
+                ("RAW", r"a $b \n"),
+                ("PORT", "8080"),
+                ("EMPTY", ""),
+                ("URL", "http://host/#anchor"),
+            ]
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+        );
+    }
+
+    #[test]
+    fn parse_reports_the_line_of_errors() {
+        assert_eq!(
+            parse("A=1\nnot a variable").unwrap_err(),
+            "line 2: expected KEY=VALUE"
+        );
+        assert_eq!(
+            parse("MY VAR=1").unwrap_err(),
+            "line 1: invalid variable name 'MY VAR'"
+        );
+        assert_eq!(parse("A=\"open").unwrap_err(), "line 1: unterminated quote");
+    }
+}
@@ -0,0 +1,181 @@
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use regex::Regex;
+
+/// Errors reported by yk
+#[derive(Debug)]
+pub enum YkError {
+    /// Missing or invalid configuration
+    Config(String),
+    /// Configuration or snippet file that cannot be parsed
+    Parse {
+        path: PathBuf,
+        line: usize,
+        column: usize,
+        /// Path of the offending field, e.g. `deploy.placeholders.env`
+        field: Option<String>,
+        message: String,
+    },
+    /// Plugin that cannot be loaded
+    Plugin {
+        name: String,
+        message: String,
+    },
+    /// Picker that cannot be started or returned invalid output
+    Picker(String),
+    /// Clipboard that cannot be accessed
+    Clipboard(String),
+    /// Command that cannot be found or executed
+    Execution(String),
+    Io(io::Error),
+}
+
+/// Result type used throughout yk
+pub type Result<T> = std::result::Result<T, YkError>;
+
+/// Remove the positions parsers write into their messages, e.g. ` at line 3 column 1`
+///
+/// `YkError::Parse` reports the position separately, in front of the message.
+pub fn strip_position(message: &str) -> String {
+    static POSITION: OnceLock<Regex> = OnceLock::new();
+    POSITION
+        .get_or_init(|| Regex::new(r" at line \d+ column \d+").expect("valid regex"))
+        .replace_all(message, "")
+        .into_owned()
+}
+
+impl YkError {
+    /// Create a parse error from a serde_json error
+    ///
+    /// `offset` is the line and column where the parsed text starts inside
+    /// the file, positions reported by serde_json are relative to it.
+    pub fn from_json(
+        path: &Path,
+        offset: (usize, usize),
+        field: Option<String>,
+        error: &serde_json::Error,
+    ) -> Self {
+        let (line, column) = if error.line() <= 1 {
+            (offset.0, offset.1 + error.column().saturating_sub(1))
+        } else {
+            (offset.0 + error.line() - 1, error.column())
+        };
+
+        YkError::parse(
+            path,
+            (line, column),
+            field.unwrap_or_default(),
+            strip_position(&error.to_string()),
+        )
+    }
+
+    /// Create a parse error at a line and column of a file
+    ///
+    /// `field` is the path of the offending field as reported by
+    /// serde_path_to_error, empty or `.` for the document itself.
+    pub fn parse(
+        path: &Path,
+        position: (usize, usize),
+        field: String,
+        message: impl Into<String>,
+    ) -> Self {
+        YkError::Parse {
+            path: path.to_path_buf(),
+            line: position.0,
+            column: position.1,
+            field: Some(field).filter(|field| !field.is_empty() && field != "."),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for YkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            YkError::Config(message)
+            | YkError::Picker(message)
+            | YkError::Clipboard(message)
+            | YkError::Execution(message) => write!(f, "{}", message),
+            YkError::Parse {
+                path,
+                line,
+                column,
+                field,
+                message,
+            } => {
+                write!(f, "{}:{}:{}: ", path.display(), line, column)?;
+                if let Some(field) = field {
+                    write!(f, "field '{}': ", field)?;
+                }
+                write!(f, "{}", message)
+            }
+            YkError::Plugin { name, message } => write!(f, "plugin '{}': {}", name, message),
+            YkError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for YkError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            YkError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for YkError {
+    fn from(e: io::Error) -> Self {
+        YkError::Io(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn json_error(text: &str) -> serde_json::Error {
+        serde_json::from_str::<serde_json::Value>(text).unwrap_err()
+    }
+
+    fn position(error: &YkError) -> (usize, usize) {
+        match error {
+            YkError::Parse { line, column, .. } => (*line, *column),
+            _ => panic!("not a parse error: {}", error),
+        }
+    }
+
+    #[test]
+    fn from_json_offsets_the_first_line_by_column() {
+        let error = YkError::from_json(Path::new("a.json"), (3, 10), None, &json_error("[1,,]"));
+        assert_eq!(position(&error), (3, 13));
+        assert_eq!(error.to_string(), "a.json:3:13: expected value");
+    }
+
+    #[test]
+    fn from_json_offsets_later_lines_by_line_only() {
+        let error = YkError::from_json(
+            Path::new("a.json"),
+            (3, 10),
+            Some("deploy".to_string()),
+            &json_error("[\n  1,\n  x]"),
+        );
+        assert_eq!(position(&error), (5, 3));
+        assert_eq!(
+            error.to_string(),
+            "a.json:5:3: field 'deploy': expected value"
+        );
+    }
+
+    #[test]
+    fn strip_position_removes_every_position() {
+        assert_eq!(
+            strip_position("did not find expected ',' at line 3 column 1, while parsing a flow sequence at line 2 column 9"),
+            "did not find expected ',', while parsing a flow sequence"
+        );
+        assert_eq!(strip_position("expected value"), "expected value");
+    }
+}
@@ -0,0 +1,584 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::de::{DeserializeOwned, IgnoredAny};
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+
+use crate::error::{strip_position, Result, YkError};
+use crate::CommandSnippet;
+
+/// Formats of configuration and snippet files
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileFormat {
+    Json,
+    Toml,
+    Yaml,
+}
+
+/// Supported extensions, in the order files are looked up
+const EXTENSIONS: [(&str, FileFormat); 4] = [
+    ("json", FileFormat::Json),
+    ("toml", FileFormat::Toml),
+    ("yaml", FileFormat::Yaml),
+    ("yml", FileFormat::Yaml),
+];
+
+impl FileFormat {
+    /// Detect the format from the file extension
+    pub fn from_path(path: &Path) -> Option<FileFormat> {
+        let extension = path.extension()?.to_string_lossy().to_lowercase();
+        EXTENSIONS
+            .iter()
+            .find(|(name, _)| *name == extension)
+            .map(|(_, format)| *format)
+    }
+}
+
+/// Find `<stem>.json`, `<stem>.toml`, `<stem>.yaml` or `<stem>.yml` in a directory
+pub fn find_file(dir: &Path, stem: &str) -> Option<PathBuf> {
+    EXTENSIONS
+        .iter()
+        .map(|(extension, _)| dir.join(format!("{}.{}", stem, extension)))
+        .find(|path| path.is_file())
+}
+
+/// Existing file with the given stem, or the JSON file to create
+pub fn resolve_file(dir: &Path, stem: &str) -> PathBuf {
+    find_file(dir, stem).unwrap_or_else(|| dir.join(format!("{}.json", stem)))
+}
+
+fn format_of(path: &Path) -> Result<FileFormat> {
+    FileFormat::from_path(path).ok_or_else(|| {
+        YkError::Config(format!(
+            "Unsupported file format {:?}, expected .json, .toml or .yaml",
+            path
+        ))
+    })
+}
+
+/// Line and column of a byte offset
+pub fn line_column(content: &str, offset: usize) -> (usize, usize) {
+    let before = &content[..offset.min(content.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before
+        .rsplit('\n')
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .count()
+        + 1;
+    (line, column)
+}
+
+/// Parse JSON text found at `offset` (line and column) inside a file
+///
+/// Errors report the position in the file and the path of the offending field.
+fn parse_json<'a, T: Deserialize<'a>>(
+    path: &Path,
+    content: &'a str,
+    offset: (usize, usize),
+) -> Result<T> {
+    let mut deserializer = serde_json::Deserializer::from_str(content);
+    serde_path_to_error::deserialize(&mut deserializer)
+        .map_err(|e| YkError::from_json(path, offset, Some(e.path().to_string()), e.inner()))
+}
+
+fn parse_toml<T: DeserializeOwned>(path: &Path, content: &str) -> Result<T> {
+    serde_path_to_error::deserialize(toml::Deserializer::new(content)).map_err(|e| {
+        let position = e
+            .inner()
+            .span()
+            .map(|span| line_column(content, span.start))
+            .unwrap_or((1, 1));
+        YkError::parse(path, position, e.path().to_string(), e.inner().message())
+    })
+}
+
+fn parse_yaml<T: DeserializeOwned>(path: &Path, content: &str) -> Result<T> {
+    serde_path_to_error::deserialize(serde_norway::Deserializer::from_str(content)).map_err(|e| {
+        let position = e
+            .inner()
+            .location()
+            .map(|location| (location.line(), location.column()))
+            .unwrap_or((1, 1));
+        YkError::parse(
+            path,
+            position,
+            e.path().to_string(),
+            strip_position(&e.inner().to_string()),
+        )
+    })
+}
+
+/// Parse a whole configuration file, the format is detected by its extension
+pub fn parse_document<T: DeserializeOwned>(path: &Path, content: &str) -> Result<T> {
+    match format_of(path)? {
+        FileFormat::Json => parse_json(path, content, (1, 1)),
+        FileFormat::Toml => parse_toml(path, content),
+        FileFormat::Yaml => parse_yaml(path, content),
+    }
+}
+
+/// Line of a top-level YAML key, e.g. `deploy:`
+fn yaml_key_line(content: &str, name: &str) -> Option<usize> {
+    let candidates = [
+        format!("{}:", name),
+        format!("\"{}\":", name),
+        format!("'{}':", name),
+    ];
+    content
+        .lines()
+        .position(|line| candidates.iter().any(|key| line.starts_with(key.as_str())))
+        .map(|index| index + 1)
+}
+
+/// Deserialize a command from an already parsed value
+///
+/// Values of TOML and YAML documents carry no positions, so errors point to
+/// the start of the command.
+fn parse_command<'de, D: serde::Deserializer<'de>>(
+    path: &Path,
+    position: (usize, usize),
+    value: D,
+) -> Result<CommandSnippet>
+where
+    D::Error: std::fmt::Display,
+{
+    serde_path_to_error::deserialize(value).map_err(|e| {
+        // toml appends the key to its message, the field is reported separately
+        let message = e.inner().to_string();
+        let message = match message.find("\nin `") {
+            Some(index) => message[..index].to_string(),
+            None => message.trim_end().to_string(),
+        };
+        YkError::parse(path, position, e.path().to_string(), message)
+    })
+}
+
+/// Split a snippet file into its commands, each parsed on its own
+///
+/// Keys starting with `$`, like `$schema`, are meant for editors and skipped.
+/// Field paths of errors are relative to the command.
+pub fn parse_commands(path: &Path, content: &str) -> Result<Vec<(String, Result<CommandSnippet>)>> {
+    parse_commands_as(path, format_of(path)?, content)
+}
+
+/// Split snippets of a known format into their commands, e.g. the output of a generator
+///
+/// `path` is only used in error messages.
+pub fn parse_commands_as(
+    path: &Path,
+    format: FileFormat,
+    content: &str,
+) -> Result<Vec<(String, Result<CommandSnippet>)>> {
+    let mut commands = Vec::new();
+    let is_command = |name: &String| !name.starts_with('$');
+
+    match format {
+        FileFormat::Json => {
+            // Commands are kept as raw text so that errors are reported at
+            // their position in the file
+            let map: BTreeMap<String, &RawValue> = parse_json(path, content, (1, 1))?;
+            for (name, raw) in map.into_iter().filter(|(name, _)| is_command(name)) {
+                let offset = raw.get().as_ptr() as usize - content.as_ptr() as usize;
+                let position = line_column(content, offset);
+                commands.push((name, parse_json(path, raw.get(), position)));
+            }
+        }
+        FileFormat::Toml => {
+            let map: BTreeMap<String, toml::Spanned<toml::Value>> = parse_toml(path, content)?;
+            for (name, value) in map.into_iter().filter(|(name, _)| is_command(name)) {
+                let position = line_column(content, value.span().start);
+                commands.push((name, parse_command(path, position, value.into_inner())));
+            }
+        }
+        FileFormat::Yaml => {
+            // An empty document is an empty snippet file
+            let map: Option<BTreeMap<String, serde_norway::Value>> = parse_yaml(path, content)?;
+            for (name, value) in map.unwrap_or_default() {
+                if !is_command(&name) {
+                    continue;
+                }
+                let position = (yaml_key_line(content, &name).unwrap_or(1), 1);
+                commands.push((name, parse_command(path, position, value)));
+            }
+        }
+    }
+
+    Ok(commands)
+}
+
+/// Whether a snippet file defines a command
+pub fn contains_command(path: &Path, content: &str, name: &str) -> Result<bool> {
+    if content.trim().is_empty() {
+        return Ok(false);
+    }
+
+    let map: Option<BTreeMap<String, IgnoredAny>> = parse_document(path, content)?;
+    Ok(map.is_some_and(|map| map.contains_key(name)))
+}
+
+fn serialize_error(e: impl std::fmt::Display) -> YkError {
+    YkError::Io(io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+}
+
+/// Serialize a whole configuration file in the format of its extension
+pub fn to_string<T: Serialize>(path: &Path, value: &T) -> Result<String> {
+    match format_of(path)? {
+        FileFormat::Json => serde_json::to_string_pretty(value).map_err(serialize_error),
+        FileFormat::Toml => toml::to_string_pretty(value).map_err(serialize_error),
+        FileFormat::Yaml => serde_norway::to_string(value).map_err(serialize_error),
+    }
+}
+
+/// Add or replace a command in the content of a snippet file
+///
+/// TOML and YAML files are edited in place, so comments and the layout of
+/// other commands are kept. JSON has no comments and is written anew.
+pub fn upsert_command(
+    path: &Path,
+    content: &str,
+    name: &str,
+    command: &CommandSnippet,
+) -> Result<String> {
+    match format_of(path)? {
+        FileFormat::Json => {
+            let mut map: serde_json::Map<String, serde_json::Value> = if content.trim().is_empty() {
+                serde_json::Map::new()
+            } else {
+                parse_json(path, content, (1, 1))?
+            };
+            map.insert(
+                name.to_string(),
+                serde_json::to_value(command).map_err(serialize_error)?,
+            );
+            serde_json::to_string_pretty(&map).map_err(serialize_error)
+        }
+        FileFormat::Toml => upsert_toml(path, content, name, command),
+        FileFormat::Yaml => {
+            let block = serde_norway::to_string(&BTreeMap::from([(name, command)]))
+                .map_err(serialize_error)?;
+            upsert_yaml(path, content, name, Some(&block))
+        }
+    }
+}
+
+/// Set or remove a top level list in the content of a configuration file
+///
+/// An empty list removes the key. Like commands, lists are edited in place in
+/// TOML and YAML files.
+pub fn set_list(path: &Path, content: &str, key: &str, values: &[String]) -> Result<String> {
+    match format_of(path)? {
+        FileFormat::Json => {
+            let mut map: serde_json::Map<String, serde_json::Value> = if content.trim().is_empty() {
+                serde_json::Map::new()
+            } else {
+                parse_json(path, content, (1, 1))?
+            };
+            if values.is_empty() {
+                map.remove(key);
+            } else {
+                map.insert(key.to_string(), values.into());
+            }
+            serde_json::to_string_pretty(&map).map_err(serialize_error)
+        }
+        FileFormat::Toml => {
+            let mut document = parse_toml_document(path, content)?;
+            if values.is_empty() {
+                document.remove(key);
+            } else {
+                document[key] = toml_edit::value(values.iter().collect::<toml_edit::Array>());
+            }
+            Ok(document.to_string())
+        }
+        FileFormat::Yaml => {
+            if values.is_empty() {
+                return upsert_yaml(path, content, key, None);
+            }
+            let block = serde_norway::to_string(&BTreeMap::from([(key, values)]))
+                .map_err(serialize_error)?;
+            upsert_yaml(path, content, key, Some(&block))
+        }
+    }
+}
+
+fn parse_toml_document(path: &Path, content: &str) -> Result<toml_edit::DocumentMut> {
+    content.parse().map_err(|e: toml_edit::TomlError| {
+        let position = e
+            .span()
+            .map(|span| line_column(content, span.start))
+            .unwrap_or((1, 1));
+        YkError::parse(path, position, String::new(), e.message())
+    })
+}
+
+fn upsert_toml(path: &Path, content: &str, name: &str, command: &CommandSnippet) -> Result<String> {
+    let mut document = parse_toml_document(path, content)?;
+
+    let mut table = toml_edit::ser::to_document(command)
+        .map_err(serialize_error)?
+        .as_table()
+        .clone();
+    table.set_implicit(false);
+
+    // A replaced command keeps the comments above it and its place in the file
+    match document.get(name).and_then(|item| item.as_table()) {
+        Some(existing) => {
+            *table.decor_mut() = existing.decor().clone();
+            if let Some(position) = existing.position() {
+                table.set_position(position);
+            }
+        }
+        None => {
+            let last = document
+                .iter()
+                .filter_map(|(_, item)| item.as_table().and_then(|table| table.position()))
+                .max()
+                .unwrap_or_default();
+            table.set_position(last + 1);
+            if !content.trim().is_empty() {
+                table.decor_mut().set_prefix("\n");
+            }
+        }
+    }
+
+    document.insert(name, toml_edit::Item::Table(table));
+    Ok(document.to_string())
+}
+
+/// Whether a YAML line starts a top level key, as opposed to content of the
+/// previous key, a comment or a document marker
+fn is_yaml_key_line(line: &str) -> bool {
+    !line.is_empty() && !line.starts_with([' ', '\t', '#', '-']) && line != "..."
+}
+
+/// Replace the block of a top level key, add it, or remove it when `block` is `None`
+///
+/// Documents written as a single flow mapping (`{a: ...}`) cannot be edited
+/// line by line and are written anew.
+fn upsert_yaml(path: &Path, content: &str, name: &str, block: Option<&str>) -> Result<String> {
+    let lines: Vec<&str> = content.lines().collect();
+
+    // `{}` written for new files is an empty flow mapping, block keys cannot follow it
+    let is_empty = lines
+        .iter()
+        .map(|line| line.trim())
+        .all(|line| line.is_empty() || line.starts_with('#') || line == "{}");
+    if is_empty {
+        let Some(block) = block else {
+            return Ok(content.to_string());
+        };
+        let mut result: Vec<&str> = lines
+            .into_iter()
+            .filter(|line| line.trim() != "{}")
+            .collect();
+        result.push(block.trim_end());
+        return Ok(format!("{}\n", result.join("\n")));
+    }
+
+    let is_flow_mapping = lines
+        .iter()
+        .map(|line| line.trim())
+        .find(|line| !line.is_empty() && !line.starts_with('#') && *line != "---")
+        .is_some_and(|line| line.starts_with('{'));
+    if is_flow_mapping {
+        let mut map: serde_norway::Mapping = parse_yaml(path, content)?;
+        match block {
+            Some(block) => {
+                let mut new: serde_norway::Mapping = parse_yaml(path, block)?;
+                if let Some(value) = new.remove(name) {
+                    map.insert(name.into(), value);
+                }
+            }
+            None => {
+                map.remove(name);
+            }
+        }
+        return serde_norway::to_string(&map).map_err(serialize_error);
+    }
+
+    match yaml_key_line(content, name) {
+        Some(line) => {
+            // The block ends before the next top level key. Comments and blank
+            // lines in front of it belong to that key and are kept, comments
+            // inside the block may start at any column.
+            let start = line - 1;
+            let mut end = lines[start + 1..]
+                .iter()
+                .position(|line| is_yaml_key_line(line))
+                .map(|index| start + 1 + index)
+                .unwrap_or(lines.len());
+            while end > start + 1 && {
+                let line = lines[end - 1].trim();
+                line.is_empty() || line.starts_with('#')
+            } {
+                end -= 1;
+            }
+            // A removed block takes the blank lines after it along
+            if block.is_none() {
+                while end < lines.len() && lines[end].trim().is_empty() {
+                    end += 1;
+                }
+            }
+
+            let mut result: Vec<&str> = lines[..start].to_vec();
+            result.extend(block.map(str::trim_end));
+            result.extend(&lines[end..]);
+            // Nor does the last block leave the blank line before it behind
+            while result.last().is_some_and(|line| line.trim().is_empty()) {
+                result.pop();
+            }
+            Ok(format!("{}\n", result.join("\n")))
+        }
+        None => match block {
+            Some(block) => {
+                let mut result = content.trim_end().to_string();
+                result.push_str("\n\n");
+                result.push_str(block);
+                Ok(result)
+            }
+            None => Ok(content.to_string()),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(args: &[&str]) -> CommandSnippet {
+        CommandSnippet {
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Names and arguments of the commands in a file, ordered by name
+    fn commands(path: &Path, content: &str) -> Vec<(String, Vec<String>)> {
+        parse_commands(path, content)
+            .unwrap()
+            .into_iter()
+            .map(|(name, command)| (name, command.unwrap().args))
+            .collect()
+    }
+
+    fn args(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn upsert_yaml_replaces_and_adds_commands() {
+        let path = Path::new("ops.yaml");
+        let content = "# Build commands\nbuild:\n  args:\n  - make\n\n# Tests\ntest:\n  args: [cargo, test]\n";
+
+        let replaced = upsert_command(path, content, "build", &command(&["make", "all"])).unwrap();
+        let added =
+            upsert_command(path, &replaced, "lint", &command(&["cargo", "clippy"])).unwrap();
+
+        assert!(added.starts_with("# Build commands\nbuild:\n"));
+        assert!(added.contains("\n# Tests\ntest:\n  args: [cargo, test]\n"));
+        assert_eq!(
+            commands(path, &added),
+            [
+                ("build".to_string(), args(&["make", "all"])),
+                ("lint".to_string(), args(&["cargo", "clippy"])),
+                ("test".to_string(), args(&["cargo", "test"])),
+            ]
+        );
+        // New commands are appended
+        assert!(added.find("lint") > added.find("test"));
+    }
+
+    #[test]
+    fn upsert_yaml_starts_empty_files() {
+        let path = Path::new("ops.yaml");
+        let content = upsert_command(path, "{}\n", "build", &command(&["make"])).unwrap();
+        assert!(!content.contains("{}"));
+        assert_eq!(
+            commands(path, &content),
+            [("build".to_string(), args(&["make"]))]
+        );
+    }
+
+    #[test]
+    fn upsert_toml_keeps_comments_and_order() {
+        let path = Path::new("ops.toml");
+        let content = "# Build commands\n[build]\nargs = [\"make\"]\n\n# Tests\n[test]\nargs = [\"cargo\", \"test\"]\n";
+
+        let replaced = upsert_command(path, content, "build", &command(&["make", "all"])).unwrap();
+        let added =
+            upsert_command(path, &replaced, "lint", &command(&["cargo", "clippy"])).unwrap();
+
+        assert!(added.starts_with("# Build commands\n[build]\n"));
+        assert!(added.contains("\n# Tests\n[test]\nargs = [\"cargo\", \"test\"]\n"));
+        assert_eq!(
+            commands(path, &added),
+            [
+                ("build".to_string(), args(&["make", "all"])),
+                ("lint".to_string(), args(&["cargo", "clippy"])),
+                ("test".to_string(), args(&["cargo", "test"])),
+            ]
+        );
+        // New commands are appended
+        assert!(added.find("lint") > added.find("test"));
+    }
+
+    #[test]
+    fn upsert_yaml_keeps_comments_inside_a_block_with_it() {
+        let path = Path::new("simple_commands.yaml");
+        let content =
+            "deploy:\n  args:\n# comment\n  - simple\n\n# Tests\ntest:\n  args: [cargo, test]\n";
+
+        let replaced = upsert_command(path, content, "deploy", &command(&["./deploy.sh"])).unwrap();
+
+        assert!(!replaced.contains("- simple"));
+        assert!(replaced.contains("\n# Tests\ntest:\n"));
+        assert_eq!(
+            commands(path, &replaced),
+            [
+                ("deploy".to_string(), args(&["./deploy.sh"])),
+                ("test".to_string(), args(&["cargo", "test"])),
+            ]
+        );
+    }
+
+    #[test]
+    fn upsert_yaml_rewrites_flow_mappings() {
+        let path = Path::new("simple_commands.yaml");
+        let content = "{build: {args: [make]}, test: {args: [cargo, test]}}\n";
+
+        let replaced = upsert_command(path, content, "build", &command(&["make", "all"])).unwrap();
+        let added =
+            upsert_command(path, &replaced, "lint", &command(&["cargo", "clippy"])).unwrap();
+
+        assert_eq!(
+            commands(path, &added),
+            [
+                ("build".to_string(), args(&["make", "all"])),
+                ("lint".to_string(), args(&["cargo", "clippy"])),
+                ("test".to_string(), args(&["cargo", "test"])),
+            ]
+        );
+    }
+
+    #[test]
+    fn set_list_round_trips() {
+        for (path, content) in [
+            ("config.yaml", "# yk\neditor: vim\nif_yank: false\n"),
+            ("config.toml", "# yk\neditor = \"vim\"\nif_yank = false\n"),
+        ] {
+            let path = Path::new(path);
+            let values = args(&["k8s", "ops"]);
+
+            let set = set_list(path, content, "disabled_plugins", &values).unwrap();
+            let config: crate::YkConfig = parse_document(path, &set).unwrap();
+            assert_eq!(config.disabled_plugins, values);
+            assert_eq!(config.editor, Path::new("vim"));
+
+            let removed = set_list(path, &set, "disabled_plugins", &[]).unwrap();
+            assert_eq!(removed, content);
+        }
+    }
+}
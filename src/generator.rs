@@ -0,0 +1,287 @@
+use std::collections::hash_map::DefaultHasher;
+use std::env;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Result, YkError};
+use crate::format::{self, FileFormat};
+use crate::history::now;
+use crate::{diagnostics, get_cache_dir, CommandSnippet};
+
+/// Name of the executable printing the snippets of a plugin
+pub const GENERATOR_FILE_STEM: &str = "generator";
+/// Environment variable holding the plugin directory while the generator runs
+const PLUGIN_DIR_ENV: &str = "YK_PLUGIN_DIR";
+/// Extensions of generators on Windows, which has no executable permission
+const WINDOWS_EXTENSIONS: [&str; 3] = ["exe", "cmd", "bat"];
+
+/// Whether generators are never run and only cached output is used
+static CACHE_ONLY: AtomicBool = AtomicBool::new(false);
+
+/// Use cached output of any age instead of running generators
+///
+/// For the preview process started by fzf: the picker process refreshed the
+/// cache just before, and a slow generator must not delay every preview.
+pub fn use_cache_only() {
+    CACHE_ONLY.store(true, Ordering::Relaxed);
+}
+
+/// Generator executable of a plugin directory
+pub fn find_generator(plugin_dir: &Path) -> Option<PathBuf> {
+    let mut candidates = vec![plugin_dir.join(GENERATOR_FILE_STEM)];
+    if cfg!(target_os = "windows") {
+        candidates.extend(
+            WINDOWS_EXTENSIONS
+                .iter()
+                .map(|extension| plugin_dir.join(format!("{}.{}", GENERATOR_FILE_STEM, extension))),
+        );
+    }
+    candidates.into_iter().find(|path| path.is_file())
+}
+
+/// Output of a generator kept between runs
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedOutput {
+    generator: PathBuf,
+    cwd: PathBuf,
+    /// Seconds since the Unix epoch
+    generated_at: u64,
+    output: String,
+}
+
+/// Cache file of a generator run in a directory
+///
+/// Generators like Makefile targets depend on the current directory, so the
+/// output is cached per directory.
+fn cache_file(generator: &Path, cwd: &Path) -> PathBuf {
+    let mut hasher = DefaultHasher::new();
+    (generator, cwd).hash(&mut hasher);
+    get_cache_dir().join(format!("generator-{:016x}.json", hasher.finish()))
+}
+
+fn load_cache(path: &Path) -> Option<CachedOutput> {
+    let content = fs::read_to_string(path).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+fn save_cache(path: &Path, cached: &CachedOutput) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(
+        path,
+        serde_json::to_string(cached).map_err(io::Error::from)?,
+    )?;
+    Ok(())
+}
+
+/// Whether cached output is younger than the TTL and the generator did not change since
+fn is_fresh(cached: &CachedOutput, generator: &Path, ttl: Duration) -> bool {
+    let modified = fs::metadata(generator)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map(|modified| modified.as_secs())
+        .unwrap_or(u64::MAX);
+    modified <= cached.generated_at && now().saturating_sub(cached.generated_at) < ttl.as_secs()
+}
+
+/// Read a pipe to the end in another thread
+///
+/// Both pipes are drained while the generator runs, so a full pipe cannot block it.
+fn read_in_background(mut pipe: impl Read + Send + 'static) -> JoinHandle<String> {
+    thread::spawn(move || {
+        let mut buffer = Vec::new();
+        let _ = pipe.read_to_end(&mut buffer);
+        String::from_utf8_lossy(&buffer).into_owned()
+    })
+}
+
+/// Run a generator in the current directory and return its output
+fn run(generator: &Path, timeout: Duration) -> Result<String> {
+    let plugin_dir = generator.parent().unwrap_or(Path::new(""));
+    let mut child = Command::new(generator)
+        .env(PLUGIN_DIR_ENV, plugin_dir)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| {
+            YkError::Execution(format!("failed to run generator {:?}: {}", generator, e))
+        })?;
+    let stdout = child.stdout.take().map(read_in_background);
+    let stderr = child.stderr.take().map(read_in_background);
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(YkError::Execution(format!(
+                "generator timed out after {}s",
+                timeout.as_secs()
+            )));
+        }
+        thread::sleep(Duration::from_millis(10));
+    };
+
+    let join = |reader: Option<JoinHandle<String>>| {
+        reader
+            .and_then(|reader| reader.join().ok())
+            .unwrap_or_default()
+    };
+    let (stdout, stderr) = (join(stdout), join(stderr));
+    if !status.success() {
+        let mut message = format!("generator failed with {}", status);
+        if let Some(line) = stderr.lines().rev().find(|line| !line.trim().is_empty()) {
+            message.push_str(&format!(": {}", line.trim()));
+        }
+        return Err(YkError::Execution(message));
+    }
+    Ok(stdout)
+}
+
+/// Parse the output of a generator
+fn parse_output(generator: &Path, output: &str) -> Result<Vec<(String, Result<CommandSnippet>)>> {
+    format::parse_commands_as(generator, FileFormat::Json, output)
+        .map_err(|e| YkError::Execution(format!("generator printed invalid snippets: {}", e)))
+}
+
+/// Commands printed by a generator, each parsed on its own
+///
+/// Output is reused while it is younger than `ttl`. When the generator fails,
+/// output cached earlier is used regardless of its age, with a warning. The
+/// output is cached even with a TTL of 0, for the preview and as a fallback.
+pub fn generate(
+    source_name: &str,
+    generator: &Path,
+    timeout: Duration,
+    ttl: Duration,
+) -> Result<Vec<(String, Result<CommandSnippet>)>> {
+    let cwd = env::current_dir().unwrap_or_default();
+    let cache_file = cache_file(generator, &cwd);
+    let cached = load_cache(&cache_file);
+
+    if let Some(ref cached) = cached {
+        if CACHE_ONLY.load(Ordering::Relaxed) || is_fresh(cached, generator, ttl) {
+            diagnostics::verbose(format!(
+                "Using the cached output of the {} generator",
+                source_name
+            ));
+            return parse_output(generator, &cached.output);
+        }
+    }
+    if CACHE_ONLY.load(Ordering::Relaxed) {
+        return Err(YkError::Execution(
+            "generator output is not cached yet".to_string(),
+        ));
+    }
+
+    let started = Instant::now();
+    let generated = run(generator, timeout)
+        .and_then(|output| parse_output(generator, &output).map(|commands| (output, commands)));
+    match generated {
+        Ok((output, commands)) => {
+            diagnostics::verbose(format!(
+                "Ran the {} generator in {:.1}s",
+                source_name,
+                started.elapsed().as_secs_f64()
+            ));
+            let cached = CachedOutput {
+                generator: generator.to_path_buf(),
+                cwd,
+                generated_at: now(),
+                output,
+            };
+            if let Err(e) = save_cache(&cache_file, &cached) {
+                diagnostics::warn(format!("Failed to cache generator output: {}", e));
+            }
+            Ok(commands)
+        }
+        Err(e) => match cached {
+            Some(cached) => {
+                diagnostics::warn(format!(
+                    "{} {}, using its output from {}s ago",
+                    source_name,
+                    e,
+                    now().saturating_sub(cached.generated_at)
+                ));
+                parse_output(generator, &cached.output)
+            }
+            None => Err(e),
+        },
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn script(dir: &Path, body: &str) -> PathBuf {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = dir.join(GENERATOR_FILE_STEM);
+        fs::write(&path, format!("#!/bin/sh\n{}\n", body)).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    #[test]
+    fn run_returns_output_and_reports_failures() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let generator = script(dir.path(), "echo \"$YK_PLUGIN_DIR\"");
+        let output = run(&generator, Duration::from_secs(10)).unwrap();
+        assert_eq!(output.trim(), dir.path().to_string_lossy());
+
+        let generator = script(dir.path(), "echo first >&2; echo broken >&2; exit 3");
+        let error = run(&generator, Duration::from_secs(10)).unwrap_err();
+        assert!(error.to_string().ends_with(": broken"), "{}", error);
+    }
+
+    #[test]
+    fn run_stops_generators_after_the_timeout() {
+        let dir = tempfile::tempdir().unwrap();
+        let generator = script(dir.path(), "sleep 10");
+
+        let started = Instant::now();
+        let error = run(&generator, Duration::from_millis(200)).unwrap_err();
+        assert!(error.to_string().contains("timed out"), "{}", error);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn is_fresh_checks_the_ttl_and_the_generator() {
+        let dir = tempfile::tempdir().unwrap();
+        let generator = script(dir.path(), "echo {}");
+        let cached = |generated_at| CachedOutput {
+            generator: generator.clone(),
+            cwd: dir.path().to_path_buf(),
+            generated_at,
+            output: "{}".to_string(),
+        };
+        let minute = Duration::from_secs(60);
+
+        assert!(is_fresh(&cached(now()), &generator, minute));
+        assert!(!is_fresh(&cached(now()), &generator, Duration::ZERO));
+        assert!(!is_fresh(&cached(now() - 120), &generator, minute));
+        // Output older than the generator was made by a previous version of it
+        assert!(!is_fresh(&cached(0), &generator, Duration::MAX));
+        assert!(!is_fresh(
+            &cached(now()),
+            &dir.path().join("missing"),
+            minute
+        ));
+    }
+}
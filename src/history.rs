@@ -0,0 +1,221 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::Result;
+use crate::FullCommandSnippet;
+
+/// What was done with a selected command
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HistoryAction {
+    Run,
+    Yank,
+}
+
+/// Single history record
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub name: String,
+    pub plugin_name: String,
+    pub config_file: PathBuf,
+    /// Seconds since the Unix epoch
+    pub timestamp: u64,
+    pub action: HistoryAction,
+    pub command: String,
+    pub exit_code: Option<i32>,
+    pub duration_ms: Option<u64>,
+    pub cwd: PathBuf,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub values: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extra_args: Vec<String>,
+}
+
+impl HistoryEntry {
+    /// Create a record for a command with filled in placeholders
+    pub fn new(
+        command: &FullCommandSnippet,
+        action: HistoryAction,
+        values: &HashMap<String, String>,
+        extra_args: &[String],
+    ) -> Self {
+        let cwd = command
+            .cwd
+            .clone()
+            .or_else(|| env::current_dir().ok())
+            .unwrap_or_default();
+
+        Self {
+            name: command.name.clone(),
+            plugin_name: command.plugin_name.clone(),
+            config_file: command.config_file.clone(),
+            timestamp: now(),
+            action,
+            command: command.complete_command.clone(),
+            exit_code: None,
+            duration_ms: None,
+            cwd,
+            values: values.clone(),
+            extra_args: extra_args.to_vec(),
+        }
+    }
+
+    /// Whether this record belongs to the given command
+    pub fn matches(&self, command: &FullCommandSnippet) -> bool {
+        self.name == command.name && self.config_file == command.config_file
+    }
+}
+
+/// Current time in seconds since the Unix epoch
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Append a record to the history file
+pub fn append(history_file: &Path, entry: &HistoryEntry) -> Result<()> {
+    if let Some(parent) = history_file.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(history_file)?;
+    writeln!(
+        file,
+        "{}",
+        serde_json::to_string(entry).map_err(io::Error::from)?
+    )?;
+    Ok(())
+}
+
+/// Load all records, oldest first
+///
+/// Lines that cannot be parsed are skipped.
+pub fn load(history_file: &Path) -> Vec<HistoryEntry> {
+    let Ok(content) = fs::read_to_string(history_file) else {
+        return Vec::new();
+    };
+
+    content
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+/// Weight of a single use depending on how long ago it happened
+fn recency_weight(age: Duration) -> f64 {
+    const HOUR: u64 = 60 * 60;
+    const DAY: u64 = 24 * HOUR;
+
+    match age.as_secs() {
+        s if s < HOUR => 4.0,
+        s if s < DAY => 2.0,
+        s if s < 7 * DAY => 1.0,
+        s if s < 30 * DAY => 0.5,
+        _ => 0.25,
+    }
+}
+
+/// Sort commands by frecency so that frequently and recently used ones come first
+///
+/// Commands with equal scores keep their relative order.
+pub fn sort_by_frecency(commands: &mut [FullCommandSnippet], entries: &[HistoryEntry]) {
+    let now = now();
+    let mut scores: HashMap<(&Path, &str), f64> = HashMap::new();
+    for entry in entries {
+        let age = Duration::from_secs(now.saturating_sub(entry.timestamp));
+        *scores
+            .entry((entry.config_file.as_path(), entry.name.as_str()))
+            .or_default() += recency_weight(age);
+    }
+
+    let score = |command: &FullCommandSnippet| {
+        scores
+            .get(&(command.config_file.as_path(), command.name.as_str()))
+            .copied()
+            .unwrap_or_default()
+    };
+    commands.sort_by(|a, b| score(b).total_cmp(&score(a)));
+}
+
+/// Format a timestamp relative to now, e.g. `5m ago`
+pub fn format_age(timestamp: u64) -> String {
+    let age = now().saturating_sub(timestamp);
+    match age {
+        s if s < 60 => format!("{}s ago", s),
+        s if s < 60 * 60 => format!("{}m ago", s / 60),
+        s if s < 24 * 60 * 60 => format!("{}h ago", s / (60 * 60)),
+        s => format!("{}d ago", s / (24 * 60 * 60)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: u64 = 24 * 60 * 60;
+
+    fn command(config_file: &str, name: &str) -> FullCommandSnippet {
+        FullCommandSnippet {
+            name: name.to_string(),
+            config_file: PathBuf::from(config_file),
+            ..Default::default()
+        }
+    }
+
+    fn used(command: &FullCommandSnippet, days_ago: u64) -> HistoryEntry {
+        let mut entry = HistoryEntry::new(command, HistoryAction::Run, &HashMap::new(), &[]);
+        entry.timestamp -= days_ago * DAY;
+        entry
+    }
+
+    fn names(commands: &[FullCommandSnippet]) -> Vec<&str> {
+        commands
+            .iter()
+            .map(|command| command.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn sort_by_frecency_weighs_frequency_and_recency() {
+        let mut commands = vec![
+            command("a.json", "unused"),
+            command("a.json", "old"),
+            command("a.json", "frequent"),
+            command("a.json", "recent"),
+            command("a.json", "also_unused"),
+        ];
+        let entries = [
+            used(&commands[1], 40),
+            used(&commands[2], 2),
+            used(&commands[2], 2),
+            used(&commands[2], 3),
+            used(&commands[3], 0),
+        ];
+
+        sort_by_frecency(&mut commands, &entries);
+        assert_eq!(
+            names(&commands),
+            ["recent", "frequent", "old", "unused", "also_unused"]
+        );
+    }
+
+    #[test]
+    fn sort_by_frecency_tells_commands_of_different_files_apart() {
+        let mut commands = vec![command("a.json", "deploy"), command("b.json", "deploy")];
+        let entries = [used(&commands[1], 0)];
+
+        sort_by_frecency(&mut commands, &entries);
+        assert_eq!(commands[0].config_file, Path::new("b.json"));
+    }
+}
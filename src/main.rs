@@ -1,32 +1,133 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::env;
 use std::fs;
 use std::io::{self, Write};
-use std::path::PathBuf;
-use std::process::{Command, Stdio};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
 
 use clap::{Parser, Subcommand};
 use clipboard::{ClipboardContext, ClipboardProvider};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+mod diagnostics;
+mod doctor;
+mod dotenv;
+mod error;
+mod format;
+mod generator;
+mod history;
+mod manifest;
+mod picker;
+mod placeholder;
+mod plugin;
+mod preview;
+mod schema;
+mod script;
+mod shell;
+mod sources;
+mod trust;
+mod workflow;
+
+use diagnostics::Verbosity;
+use error::{Result, YkError};
+use history::{HistoryAction, HistoryEntry};
+use manifest::PluginManifest;
+use picker::{create_picker, PickOptions, PickerBackend, PickerItem};
+use placeholder::{collect_placeholders, prompt_placeholders, Placeholder, PlaceholderSpec};
+use schema::SchemaKind;
+use shell::{Shell, ShellConfig};
+use sources::{PluginFile, Scope};
+use workflow::{FailurePolicy, ResolvedStep, WorkflowStep};
+
 // Define configuration file constants
-const CONFIG_DIR_NAME: &str = ".config/yk";
-const CONFIG_FILE_NAME: &str = "config.json";
+const APP_DIR_NAME: &str = "yk";
+const CONFIG_DIR_ENV: &str = "YK_CONFIG_DIR";
+const CONFIG_FILE_STEM: &str = "config";
 const PLUGINS_DIR_NAME: &str = "plugins";
-const SIMPLE_COMMAND_FILE_NAME: &str = "simple_commands.json";
-
-// Define delimiter constants
-const FZF_DELIMITER: &str = "🍃────🍃";
+const SIMPLE_COMMAND_FILE_STEM: &str = "simple_commands";
+const HISTORY_FILE_NAME: &str = "history.jsonl";
+const TRUST_FILE_NAME: &str = "trusted_projects.json";
+const PLUGIN_LOCK_FILE_NAME: &str = "plugins.lock.json";
+const CACHE_DIR_NAME: &str = "cache";
+
+/// Configuration directory, resolved once per run
+static CONFIG_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Use a configuration directory for the rest of the run
+///
+/// Has no effect once the directory has been set.
+fn set_config_dir(dir: PathBuf) {
+    let _ = CONFIG_DIR.set(dir);
+}
 
 /// Get configuration directory path
+///
+/// The directory is set by `main` before any command that uses it runs.
 fn get_config_dir() -> PathBuf {
-    let home = env::home_dir().expect("Failed to get home directory");
-    home.join(CONFIG_DIR_NAME)
+    CONFIG_DIR
+        .get()
+        .cloned()
+        .expect("configuration directory is resolved at startup")
+}
+
+/// Non-empty environment variable
+fn env_path(name: &str) -> Option<PathBuf> {
+    env::var_os(name)
+        .filter(|value| !value.is_empty())
+        .map(PathBuf::from)
+}
+
+/// Home directory of the current user
+fn home_dir(var: impl Fn(&str) -> Option<PathBuf>) -> Option<PathBuf> {
+    if cfg!(target_os = "windows") {
+        var("USERPROFILE").or_else(|| var("HOME"))
+    } else {
+        var("HOME")
+    }
+}
+
+/// Resolve the configuration directory from environment variables read with `var`
+///
+/// `YK_CONFIG_DIR` takes precedence, then `%APPDATA%\yk` on Windows and
+/// `$XDG_CONFIG_HOME/yk` or `~/.config/yk` elsewhere. Without any of them
+/// there is no safe default: a shared directory like `/tmp/yk` could be
+/// prepared by another user, so an error asks for the directory instead.
+fn resolve_config_dir(var: impl Fn(&str) -> Option<PathBuf>) -> Result<PathBuf> {
+    if let Some(dir) = var(CONFIG_DIR_ENV) {
+        return Ok(dir);
+    }
+
+    // Directory used before platform conventions were followed
+    let legacy_dir = home_dir(&var).map(|home| home.join(".config").join(APP_DIR_NAME));
+
+    let platform_dir = if cfg!(target_os = "windows") {
+        var("APPDATA").map(|dir| dir.join(APP_DIR_NAME))
+    } else {
+        // Relative values are invalid according to the XDG specification
+        var("XDG_CONFIG_HOME")
+            .filter(|dir| dir.is_absolute())
+            .map(|dir| dir.join(APP_DIR_NAME))
+            .or_else(|| legacy_dir.clone())
+    };
+
+    match (platform_dir, legacy_dir) {
+        (Some(dir), Some(legacy_dir)) if !dir.exists() && legacy_dir.is_dir() => Ok(legacy_dir),
+        (Some(dir), _) => Ok(dir),
+        (None, Some(legacy_dir)) => Ok(legacy_dir),
+        (None, None) => Err(YkError::Config(format!(
+            "Cannot determine the home directory, set {} or use --config-dir",
+            CONFIG_DIR_ENV
+        ))),
+    }
 }
 
-/// Get configuration file path
+/// Get configuration file path, `config.json`, `config.toml` or `config.yaml`
 fn get_config_file() -> PathBuf {
-    get_config_dir().join(CONFIG_FILE_NAME)
+    format::resolve_file(&get_config_dir(), CONFIG_FILE_STEM)
 }
 
 /// Get plugins directory path
@@ -34,76 +135,382 @@ fn get_plugins_dir() -> PathBuf {
     get_config_dir().join(PLUGINS_DIR_NAME)
 }
 
-/// Get simple commands configuration file path
+/// Get simple commands configuration file path, in any supported format
 fn get_simple_command_file() -> PathBuf {
-    get_config_dir().join(SIMPLE_COMMAND_FILE_NAME)
+    format::resolve_file(&get_config_dir(), SIMPLE_COMMAND_FILE_STEM)
+}
+
+/// Get execution history file path
+fn get_history_file() -> PathBuf {
+    get_config_dir().join(HISTORY_FILE_NAME)
+}
+
+/// Get the lockfile of plugins installed with `yk plugin install`
+fn get_plugin_lock_file() -> PathBuf {
+    get_config_dir().join(PLUGIN_LOCK_FILE_NAME)
+}
+
+/// Get the directory of cached generator output
+fn get_cache_dir() -> PathBuf {
+    get_config_dir().join(CACHE_DIR_NAME)
+}
+
+/// Get the file of trusted project directories
+fn get_trust_file() -> PathBuf {
+    get_config_dir().join(TRUST_FILE_NAME)
 }
 
 /// YK configuration file data structure
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(default)]
 pub struct YkConfig {
+    /// JSON Schema used by editors for completion and validation
+    #[serde(rename = "$schema", skip_serializing_if = "Option::is_none")]
+    pub schema: Option<String>,
+    /// fzf executable used by the picker
     pub fzf_executable: PathBuf,
-    pub rg_executable: PathBuf,
+    /// Editor used by `yk find -e`
     pub editor: PathBuf,
+    /// Execute the selected command
     pub if_run: bool,
+    /// Ask before executing the selected command
     pub if_run_confirm: bool,
+    /// Copy the selected command to the clipboard
     pub if_yank: bool,
+    /// Shell used for `if_shell` commands, the platform shell when not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shell: Option<ShellConfig>,
+    /// Picker backend
+    pub picker: PickerBackend,
+    /// Additional snippet files, or directories laid out like `plugins`,
+    /// relative to the configuration directory
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub snippet_paths: Vec<PathBuf>,
+    /// Plugins that are not loaded
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub disabled_plugins: Vec<String>,
+    /// Plugins loaded although their manifest disables them
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub enabled_plugins: Vec<String>,
+    /// Seconds a plugin generator may run before it is stopped
+    pub generator_timeout: u64,
+    /// Seconds the output of a plugin generator is reused, 0 runs it every time
+    pub generator_cache_ttl: u64,
 }
 
 impl Default for YkConfig {
     fn default() -> Self {
         Self {
             fzf_executable: PathBuf::from("fzf"),
-            rg_executable: PathBuf::from("rg"),
             editor: PathBuf::from("hx"),
+            schema: None,
             if_run: true,
             if_run_confirm: true,
             if_yank: true,
+            shell: None,
+            picker: PickerBackend::Auto,
+            snippet_paths: Vec::new(),
+            disabled_plugins: Vec::new(),
+            enabled_plugins: Vec::new(),
+            generator_timeout: 5,
+            generator_cache_ttl: 300,
+        }
+    }
+}
+
+impl YkConfig {
+    /// Whether a plugin is loaded, `disabled_plugins` wins over `enabled_plugins`
+    ///
+    /// Without a manifest only `disabled_plugins` is considered.
+    pub fn is_plugin_enabled(&self, name: &str, manifest: Option<&PluginManifest>) -> bool {
+        let listed = |names: &[String]| names.iter().any(|listed| listed == name);
+        if listed(&self.disabled_plugins) {
+            return false;
+        }
+        !manifest.is_some_and(|manifest| manifest.disabled) || listed(&self.enabled_plugins)
+    }
+
+    /// Default configuration referencing the schema written by `yk init`
+    pub fn with_schema() -> Self {
+        Self {
+            schema: Some(format!("./{}", schema::CONFIG_SCHEMA_FILE_NAME)),
+            ..Self::default()
         }
     }
 }
 
 /// Command snippet data structure
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
 pub struct CommandSnippet {
+    /// Labels matched by the picker
     #[serde(default)]
     pub labels: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
+    /// Program to execute
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub executable: Option<PathBuf>,
+    /// Script passed to the executable, relative to the snippet file
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub entry_point: Option<PathBuf>,
+    /// Arguments, shell code when `if_shell` is set
     #[serde(default)]
     pub args: Vec<String>,
+    /// Execute in shell
     #[serde(default)]
     pub if_shell: bool,
+    /// Declarations of `{{name}}` placeholders
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub placeholders: HashMap<String, PlaceholderSpec>,
+    /// Working directory, relative to the snippet file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<PathBuf>,
+    /// Environment variables set for the command
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub env: HashMap<String, String>,
+    /// Start from an empty environment
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub env_clear: bool,
+    /// Dotenv file to load, relative to the snippet file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub env_file: Option<PathBuf>,
+    /// Shell overriding the global `shell` setting
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shell: Option<ShellConfig>,
+    /// Inline script, run by `interpreter` with `args` as its arguments;
+    /// `executable`, `entry_point` and `if_shell` are ignored when set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub script: Option<String>,
+    /// Program running the script, e.g. `bash`, `python3`, `node` or `pwsh`;
+    /// the shell when not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interpreter: Option<PathBuf>,
+    /// Steps of a workflow, other fields are ignored when set
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub steps: Vec<WorkflowStep>,
+    /// What a workflow does when a step fails, `stop` by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_failure: Option<FailurePolicy>,
 }
 
 /// Single plugin data structure
 #[derive(Debug, Clone)]
 pub struct Plugin {
+    pub name: String,
     pub plugin_dir: PathBuf,
     pub config_file: PathBuf,
+    pub scope: Scope,
+    pub manifest: Option<PluginManifest>,
     pub commands: HashMap<String, CommandSnippet>,
 }
 
 /// Complete command snippet data structure
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct FullCommandSnippet {
+    /// Qualified id `plugin:name`, kept when a workflow step renames the command
+    pub id: String,
     pub name: String,
+    pub plugin_name: String,
     pub complete_command: String,
     pub config_file: PathBuf,
+    pub scope: Scope,
+    /// Id of the system command hidden by this one
+    pub shadows: Option<String>,
+    /// Why the command cannot run, e.g. a missing requirement of its plugin
+    pub disabled: Option<String>,
     pub labels: Vec<String>,
     pub description: Option<String>,
     pub executable: Option<PathBuf>,
     pub entry_point: Option<PathBuf>,
     pub args: Vec<String>,
     pub if_shell: bool,
+    pub placeholders: HashMap<String, PlaceholderSpec>,
+    pub cwd: Option<PathBuf>,
+    pub env: HashMap<String, String>,
+    pub env_clear: bool,
+    pub env_file: Option<PathBuf>,
+    pub shell: Shell,
+    pub script: Option<String>,
+    pub interpreter: Option<PathBuf>,
+    /// Steps of a workflow as written in the configuration file
+    pub workflow: Vec<WorkflowStep>,
+    /// Steps of a workflow resolved to the commands they run
+    pub steps: Vec<ResolvedStep>,
+    pub on_failure: FailurePolicy,
+}
+
+impl FullCommandSnippet {
+    /// Whether this snippet is a workflow composed of other snippets
+    pub fn is_workflow(&self) -> bool {
+        !self.workflow.is_empty()
+    }
+
+    /// Fail if the command, or a step of it, is disabled
+    pub fn ensure_enabled(&self) -> Result<()> {
+        let disabled = std::iter::once(self)
+            .chain(self.steps.iter().map(|step| &step.command))
+            .find_map(|command| command.disabled.as_ref().map(|reason| (command, reason)));
+        match disabled {
+            Some((command, reason)) => Err(YkError::Plugin {
+                name: command.plugin_name.clone(),
+                message: format!("command '{}' is disabled, {}", command.name, reason),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Whether this snippet runs an inline script
+    pub fn is_script(&self) -> bool {
+        self.script.is_some()
+    }
+
+    /// Program running the inline script, the shell when not configured
+    pub fn script_interpreter(&self) -> &Path {
+        self.interpreter.as_deref().unwrap_or(&self.shell.program)
+    }
+
+    /// Argument vector used when not executing in shell
+    pub fn argv(&self) -> Vec<String> {
+        build_argv(&self.executable, &self.entry_point, &self.args)
+    }
+
+    /// Rebuild `complete_command` after executable, entry point or arguments changed
+    pub fn refresh_complete_command(&mut self) {
+        if self.is_workflow() {
+            let lines: Vec<&str> = self
+                .steps
+                .iter()
+                .map(|step| step.command.complete_command.as_str())
+                .collect();
+            self.complete_command = lines.join("\n");
+            return;
+        }
+
+        // The script itself is what gets copied
+        if let Some(ref script) = self.script {
+            self.complete_command = script.clone();
+            return;
+        }
+
+        self.complete_command = build_complete_command(
+            &self.executable,
+            &self.entry_point,
+            &self.args,
+            self.if_shell,
+            &self.shell,
+        );
+    }
+
+    /// Placeholders used in executable, entry point, arguments, working
+    /// directory and environment variables
+    ///
+    /// Placeholders of workflows are those of their steps, a name used by
+    /// several steps is asked for once.
+    pub fn placeholders(&self) -> Vec<Placeholder> {
+        if self.is_workflow() {
+            let mut placeholders: Vec<Placeholder> = Vec::new();
+            for step in &self.steps {
+                for placeholder in step.command.placeholders() {
+                    match placeholders.iter_mut().find(|p| p.name == placeholder.name) {
+                        Some(existing) => {
+                            if existing.default.is_none() {
+                                existing.default = placeholder.default;
+                            }
+                        }
+                        None => placeholders.push(placeholder),
+                    }
+                }
+            }
+            return placeholders;
+        }
+
+        let paths: Vec<String> = [
+            &self.executable,
+            &self.entry_point,
+            &self.interpreter,
+            &self.cwd,
+            &self.env_file,
+        ]
+        .into_iter()
+        .flatten()
+        .map(|p| p.to_string_lossy().to_string())
+        .collect();
+
+        // Sort environment variables so the prompt order is stable
+        let mut env: Vec<(&String, &String)> = self.env.iter().collect();
+        env.sort();
+
+        collect_placeholders(
+            paths
+                .iter()
+                .chain(self.script.iter())
+                .chain(self.args.iter())
+                .chain(env.into_iter().map(|(_, value)| value))
+                .map(String::as_str),
+        )
+    }
+
+    /// Append extra arguments
+    ///
+    /// Arguments of shell commands are shell code, so extra ones are quoted.
+    /// Scripts receive them as they are.
+    pub fn append_args(&mut self, extra_args: &[String]) {
+        if extra_args.is_empty() {
+            return;
+        }
+
+        for arg in extra_args {
+            let arg = if self.if_shell && !self.is_script() {
+                self.shell.quote(arg)
+            } else {
+                arg.clone()
+            };
+            self.args.push(arg);
+        }
+        self.refresh_complete_command();
+    }
+
+    /// Create a copy with placeholders replaced by the given values
+    pub fn fill_placeholders(&self, values: &HashMap<String, String>) -> FullCommandSnippet {
+        let substitute_path =
+            |p: &PathBuf| PathBuf::from(placeholder::substitute(&p.to_string_lossy(), values));
+        // Relative paths filled in from a template are relative to the snippet file, like others
+        let base_dir = self.config_file.parent().unwrap_or(Path::new(""));
+        let resolve_path = |p: &PathBuf| base_dir.join(substitute_path(p));
+
+        let mut filled = self.clone();
+        filled.executable = self.executable.as_ref().map(substitute_path);
+        filled.entry_point = self.entry_point.as_ref().map(resolve_path);
+        filled.interpreter = self.interpreter.as_ref().map(substitute_path);
+        filled.script = self
+            .script
+            .as_ref()
+            .map(|script| placeholder::substitute(script, values));
+        filled.cwd = self.cwd.as_ref().map(resolve_path);
+        filled.env_file = self.env_file.as_ref().map(resolve_path);
+        filled.env = self
+            .env
+            .iter()
+            .map(|(key, value)| (key.clone(), placeholder::substitute(value, values)))
+            .collect();
+        filled.args = self
+            .args
+            .iter()
+            .map(|arg| placeholder::substitute(arg, values))
+            .collect();
+        filled.steps = workflow::fill_steps(&self.steps, values);
+        filled.refresh_complete_command();
+        filled
+    }
 }
 
 /// All command snippets data structure
 #[derive(Debug, Clone)]
 pub struct AllCommandSnippets {
     pub commands: Vec<FullCommandSnippet>,
+    /// System commands hidden by a user or project command of the same name,
+    /// only reachable by their qualified id
+    pub shadowed: Vec<FullCommandSnippet>,
 }
 
 impl Default for AllCommandSnippets {
@@ -116,12 +523,13 @@ impl AllCommandSnippets {
     pub fn new() -> Self {
         Self {
             commands: Vec::new(),
+            shadowed: Vec::new(),
         }
     }
 }
 
 /// Initialize configuration files and directories
-pub fn init_config() -> Result<(), Box<dyn std::error::Error>> {
+pub fn init_config() -> Result<()> {
     let config_dir = get_config_dir();
     let plugins_dir = get_plugins_dir();
     let config_file = get_config_file();
@@ -131,11 +539,14 @@ pub fn init_config() -> Result<(), Box<dyn std::error::Error>> {
     fs::create_dir_all(&config_dir)?;
     fs::create_dir_all(&plugins_dir)?;
 
+    // Schemas are generated, so they are always brought up to date
+    schema::write_schemas(&config_dir)?;
+    println!("Wrote JSON schemas to {:?}", config_dir);
+
     // Handle configuration file
     if !config_file.exists() {
-        let config = YkConfig::default();
-        let serialized = serde_json::to_string_pretty(&config)?;
-        fs::write(&config_file, serialized)?;
+        let config = YkConfig::with_schema();
+        fs::write(&config_file, format::to_string(&config_file, &config)?)?;
         println!("Created default configuration file: {:?}", config_file);
     } else {
         print!(
@@ -148,9 +559,8 @@ pub fn init_config() -> Result<(), Box<dyn std::error::Error>> {
         io::stdin().read_line(&mut input)?;
 
         if input.trim().to_lowercase() == "y" {
-            let config = YkConfig::default();
-            let serialized = serde_json::to_string_pretty(&config)?;
-            fs::write(&config_file, serialized)?;
+            let config = YkConfig::with_schema();
+            fs::write(&config_file, format::to_string(&config_file, &config)?)?;
             println!("Configuration file updated");
         } else {
             println!("Skipped configuration file update");
@@ -159,7 +569,11 @@ pub fn init_config() -> Result<(), Box<dyn std::error::Error>> {
 
     // Create simple commands configuration file
     if !simple_command_file.exists() {
-        fs::write(&simple_command_file, "{}")?;
+        let content = serde_json::json!({
+            "$schema": format!("./{}", schema::SNIPPETS_SCHEMA_FILE_NAME)
+        });
+        let serialized = serde_json::to_string_pretty(&content).map_err(io::Error::from)?;
+        fs::write(&simple_command_file, serialized)?;
         println!(
             "Created simple commands configuration file: {:?}",
             simple_command_file
@@ -170,21 +584,22 @@ pub fn init_config() -> Result<(), Box<dyn std::error::Error>> {
 }
 
 /// Load YK configuration file
-pub fn load_config() -> Result<YkConfig, Box<dyn std::error::Error>> {
+pub fn load_config() -> Result<YkConfig> {
     let config_file = get_config_file();
 
     if !config_file.exists() {
-        println!("Configuration file {:?} does not exist", config_file);
-        return Err("Configuration file does not exist".into());
+        return Err(YkError::Config(format!(
+            "Configuration file {:?} does not exist, run `yk init` first",
+            config_file
+        )));
     }
 
     let file_content = fs::read_to_string(&config_file)?;
-    let config: YkConfig = serde_json::from_str(&file_content)?;
-    Ok(config)
+    format::parse_document(&config_file, &file_content)
 }
 
 /// Create new simple command
-pub fn new_command() -> Result<(), Box<dyn std::error::Error>> {
+pub fn new_command() -> Result<()> {
     let simple_command_file = get_simple_command_file();
 
     // Interactive input
@@ -194,7 +609,7 @@ pub fn new_command() -> Result<(), Box<dyn std::error::Error>> {
     io::stdin().read_line(&mut name)?;
     let name = name.trim();
     if name.is_empty() {
-        return Err("Command name cannot be empty".into());
+        return Err(YkError::Config("Command name cannot be empty".to_string()));
     }
 
     print!("Enter command labels (space-separated): ");
@@ -254,22 +669,18 @@ pub fn new_command() -> Result<(), Box<dyn std::error::Error>> {
         entry_point,
         args,
         if_shell,
+        ..Default::default()
     };
 
-    // Load existing configuration
-    let mut config: HashMap<String, CommandSnippet> = if simple_command_file.exists() {
-        let file_content = fs::read_to_string(&simple_command_file)?;
-        if file_content.trim().is_empty() {
-            HashMap::new()
-        } else {
-            serde_json::from_str(&file_content)?
-        }
+    // Other commands, comments and keys like `$schema` are kept as they are
+    let file_content = if simple_command_file.exists() {
+        fs::read_to_string(&simple_command_file)?
     } else {
-        HashMap::new()
+        String::new()
     };
 
     // Check if command name already exists
-    if config.contains_key(name) {
+    if format::contains_command(&simple_command_file, &file_content, name)? {
         print!("Command '{}' already exists, overwrite? (y/N): ", name);
         io::stdout().flush()?;
         let mut confirm = String::new();
@@ -280,12 +691,10 @@ pub fn new_command() -> Result<(), Box<dyn std::error::Error>> {
         }
     }
 
-    // Add new command
-    config.insert(name.to_string(), command_snippet);
-
-    // Save configuration
-    let serialized = serde_json::to_string_pretty(&config)?;
-    fs::write(&simple_command_file, serialized)?;
+    // Add new command and save configuration
+    let updated =
+        format::upsert_command(&simple_command_file, &file_content, name, &command_snippet)?;
+    fs::write(&simple_command_file, updated)?;
 
     println!(
         "Created simple command '{}' and saved to {:?}",
@@ -295,357 +704,1068 @@ pub fn new_command() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+/// Commands parsed from a snippet file
+#[derive(Debug)]
+pub struct SnippetFile {
+    pub commands: HashMap<String, CommandSnippet>,
+    /// Commands that cannot be parsed, by name
+    pub errors: Vec<(String, YkError)>,
+}
+
+/// Parse the commands of a snippet file
+///
+/// Commands that cannot be parsed are reported next to the valid ones
+/// instead of failing the whole file.
+pub fn parse_snippet_file(config_file: &Path) -> Result<SnippetFile> {
+    let base_dir = config_file.parent().ok_or_else(|| {
+        YkError::Config(format!(
+            "Failed to get directory of configuration file {:?}",
+            config_file
+        ))
+    })?;
+
+    let file_content = fs::read_to_string(config_file)?;
+    collect_snippets(
+        base_dir,
+        format::parse_commands(config_file, &file_content)?,
+    )
+}
+
+/// Run the generator of a plugin and parse the commands it prints
+///
+/// Generators of projects only run once the project is trusted, as listing
+/// commands must not run code of a freshly cloned repository.
+pub fn generate_snippets(
+    config: &YkConfig,
+    plugin_file: &PluginFile,
+    manifest: Option<&PluginManifest>,
+) -> Result<SnippetFile> {
+    let generator = &plugin_file.config_file;
+    if plugin_file.scope == Scope::Project {
+        let trusted = match sources::project_dir_of(generator) {
+            Some(project_dir) => trust::is_trusted(&project_dir)?,
+            None => false,
+        };
+        if !trusted {
+            return Err(YkError::Config(
+                "generator of an untrusted project is not run, run `yk trust` to allow it"
+                    .to_string(),
+            ));
+        }
+    }
+
+    let ttl = manifest
+        .and_then(|manifest| manifest.cache_ttl)
+        .unwrap_or(config.generator_cache_ttl);
+    let parsed = generator::generate(
+        &plugin_file.source_name(),
+        generator,
+        Duration::from_secs(config.generator_timeout),
+        Duration::from_secs(ttl),
+    )?;
+    collect_snippets(generator.parent().unwrap_or(Path::new("")), parsed)
+}
+
+/// Keep the parsed commands of a snippet file or generator and their errors
+///
+/// Relative paths of commands are resolved against `base_dir`.
+fn collect_snippets(
+    base_dir: &Path,
+    parsed: Vec<(String, Result<CommandSnippet>)>,
+) -> Result<SnippetFile> {
+    let mut commands = HashMap::new();
+    let mut errors = Vec::new();
+    for (name, parsed) in parsed {
+        match parsed {
+            Ok(mut cmd_snippet) => {
+                // Convert entry_point, cwd and env_file to absolute paths relative to
+                // configuration file directory. Paths with placeholders are resolved
+                // once they are filled in, see `fill_placeholders`.
+                for path in [
+                    &mut cmd_snippet.entry_point,
+                    &mut cmd_snippet.cwd,
+                    &mut cmd_snippet.env_file,
+                ]
+                .into_iter()
+                .flatten()
+                {
+                    if path.is_relative() && !placeholder::is_template(&path.to_string_lossy()) {
+                        *path = base_dir.join(&path);
+                    }
+                }
+
+                commands.insert(name, cmd_snippet);
+            }
+            Err(YkError::Parse {
+                path,
+                line,
+                column,
+                field,
+                message,
+            }) => {
+                // Report the field relative to the file, e.g. `deploy.args`
+                let field = match field {
+                    Some(field) => format!("{}.{}", name, field),
+                    None => name.clone(),
+                };
+                let error = YkError::Parse {
+                    path,
+                    line,
+                    column,
+                    field: Some(field),
+                    message,
+                };
+                errors.push((name, error));
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(SnippetFile { commands, errors })
+}
+
 /// Generic function to load commands from configuration file
 pub fn load_commands_from_config(
-    config_file: &PathBuf,
-    source_name: &str,
-) -> Result<Option<Plugin>, Box<dyn std::error::Error>> {
+    config: &YkConfig,
+    plugin_file: &PluginFile,
+) -> Result<Option<Plugin>> {
+    let config_file = &plugin_file.config_file;
+    let source_name = plugin_file.source_name();
+
+    if !config.is_plugin_enabled(&plugin_file.name, None) {
+        diagnostics::verbose(format!("Skipped {}, it is disabled", source_name));
+        return Ok(None);
+    }
+
     if !config_file.exists() {
-        println!(
-            "Warning: {} configuration file {:?} does not exist",
+        diagnostics::warn(format!(
+            "{} configuration file {:?} does not exist",
             source_name, config_file
-        );
+        ));
         return Ok(None);
     }
 
     if !config_file.is_file() {
-        println!(
-            "Warning: {} configuration file {:?} is not a valid file",
+        diagnostics::warn(format!(
+            "{} configuration file {:?} is not a valid file",
             source_name, config_file
-        );
+        ));
         return Ok(None);
     }
 
-    let base_dir = config_file
-        .parent()
-        .ok_or("Failed to get configuration file directory")?;
-
-    let file_content = fs::read_to_string(config_file)?;
-    let data: serde_json::Value = serde_json::from_str(&file_content)?;
+    // The manifest comes first, so that disabled plugins are not even parsed
+    let plugin_dir = config_file.parent().unwrap_or(Path::new(""));
+    let manifest = manifest::load(plugin_file)?;
+    if !config.is_plugin_enabled(&plugin_file.name, manifest.as_ref()) {
+        diagnostics::verbose(format!("Skipped {}, its manifest disables it", source_name));
+        return Ok(None);
+    }
 
-    let commands_map = match data {
-        serde_json::Value::Object(map) => map,
-        _ => {
-            println!(
-                "Warning: {} configuration file format error: expected object format",
-                source_name
-            );
-            return Ok(None);
-        }
+    let SnippetFile { commands, errors } = if plugin_file.generator {
+        generate_snippets(config, plugin_file, manifest.as_ref())?
+    } else {
+        parse_snippet_file(config_file)?
     };
-
-    let mut commands = HashMap::new();
-    for (name, cmd_data) in commands_map {
-        if let Ok(mut cmd_snippet) = serde_json::from_value::<CommandSnippet>(cmd_data) {
-            // Convert entry_point to absolute path relative to configuration file directory
-            if let Some(ref mut entry_point) = cmd_snippet.entry_point {
-                if entry_point.is_relative() {
-                    *entry_point = base_dir.join(&entry_point);
-                }
-            }
-
-            commands.insert(name, cmd_snippet);
-        } else {
-            println!("Warning: {} command '{}' format error", source_name, name);
-        }
+    for (name, e) in errors {
+        diagnostics::warn(format!("{} command '{}' skipped: {}", source_name, name, e));
     }
 
     if commands.is_empty() {
-        println!(
-            "Warning: {} configuration file has no valid commands",
+        diagnostics::warn(format!(
+            "{} configuration file has no valid commands",
             source_name
-        );
+        ));
         return Ok(None);
     }
 
+    diagnostics::verbose(format!(
+        "Loaded {} commands of {} from {:?}",
+        commands.len(),
+        source_name,
+        config_file
+    ));
+
     Ok(Some(Plugin {
-        plugin_dir: base_dir.to_path_buf(),
+        name: plugin_file.name.clone(),
+        plugin_dir: plugin_dir.to_path_buf(),
         config_file: config_file.clone(),
+        scope: plugin_file.scope,
+        manifest,
         commands,
     }))
 }
 
-/// Load simple commands plugin
-pub fn load_simple_plugin() -> Result<Option<Plugin>, Box<dyn std::error::Error>> {
-    let simple_command_file = get_simple_command_file();
-    load_commands_from_config(&simple_command_file, "simple commands")
-}
-
-/// Load all plugins
-pub fn load_plugins() -> Result<Vec<Plugin>, Box<dyn std::error::Error>> {
-    let plugins_dir = get_plugins_dir();
+/// Load simple commands, plugins, configured snippet paths and the snippets
+/// of the current project
+pub fn load_plugins(config: &YkConfig) -> Vec<Plugin> {
     let mut plugins = Vec::new();
 
-    if !plugins_dir.exists() {
-        return Ok(plugins);
+    for plugin_file in sources::plugin_files(config) {
+        match load_commands_from_config(config, &plugin_file) {
+            Ok(Some(plugin)) => plugins.push(plugin),
+            Ok(None) => {}
+            Err(e) => diagnostics::warn(YkError::Plugin {
+                name: plugin_file.name,
+                message: e.to_string(),
+            }),
+        }
     }
 
-    if let Ok(entries) = fs::read_dir(&plugins_dir) {
-        for entry in entries.flatten() {
-            if entry.file_type().map(|ft| ft.is_dir()).unwrap_or(false) {
-                let plugin_dir = entry.path();
-                let config_file =
-                    plugin_dir.join(format!("{}.json", entry.file_name().to_string_lossy()));
+    plugins
+}
 
-                if let Ok(Some(plugin)) = load_commands_from_config(
-                    &config_file,
-                    &format!("plugin {}", entry.file_name().to_string_lossy()),
-                ) {
-                    plugins.push(plugin);
-                }
-            }
-        }
+/// Build the argument vector from executable, entry point and arguments
+pub fn build_argv(
+    executable: &Option<PathBuf>,
+    entry_point: &Option<PathBuf>,
+    args: &[String],
+) -> Vec<String> {
+    let mut argv = Vec::new();
+
+    if let Some(ref executable) = executable {
+        argv.push(executable.to_string_lossy().to_string());
+    }
+    if let Some(ref entry_point) = entry_point {
+        argv.push(entry_point.to_string_lossy().to_string());
     }
+    argv.extend(args.iter().cloned());
 
-    Ok(plugins)
+    argv
 }
 
-/// Load all commands
-pub fn load_commands() -> Result<AllCommandSnippets, Box<dyn std::error::Error>> {
-    let simple_plugin = load_simple_plugin()?;
-    let plugins = load_plugins()?;
+/// Join executable, entry point and arguments into the complete command
+///
+/// Arguments of shell commands are shell code and kept as written, everything
+/// else is quoted for the shell so that the command line reproduces the same argv.
+pub fn build_complete_command(
+    executable: &Option<PathBuf>,
+    entry_point: &Option<PathBuf>,
+    args: &[String],
+    if_shell: bool,
+    shell: &Shell,
+) -> String {
+    if !if_shell {
+        return shell.join_args(&build_argv(executable, entry_point, args));
+    }
 
-    let mut all_plugins = Vec::new();
-    if let Some(plugin) = simple_plugin {
-        all_plugins.push(plugin);
+    let mut command_parts = shell.join_args(&build_argv(executable, entry_point, &[]));
+    for arg in args {
+        if !command_parts.is_empty() {
+            command_parts.push(' ');
+        }
+        command_parts.push_str(arg);
     }
-    all_plugins.extend(plugins);
+    command_parts
+}
 
+/// Load all commands
+pub fn load_commands(config: &YkConfig) -> Result<AllCommandSnippets> {
     let mut all_commands = AllCommandSnippets::new();
 
-    for plugin in all_plugins {
-        for (name, cmd_snippet) in plugin.commands {
-            let mut command_parts = Vec::new();
-
-            if let Some(ref executable) = cmd_snippet.executable {
-                command_parts.push(executable.to_string_lossy().to_string());
-            }
-            if let Some(ref entry_point) = cmd_snippet.entry_point {
-                command_parts.push(entry_point.to_string_lossy().to_string());
-            }
-            if !cmd_snippet.args.is_empty() {
-                command_parts.extend(cmd_snippet.args.clone());
-            }
+    for plugin in load_plugins(config) {
+        // Commands of plugins whose requirements are not met stay visible
+        let disabled = plugin
+            .manifest
+            .as_ref()
+            .and_then(PluginManifest::unmet_requirement);
+        if let Some(ref reason) = disabled {
+            diagnostics::verbose(format!(
+                "Commands of plugin {} are disabled: {}",
+                plugin.name, reason
+            ));
+        }
 
-            let complete_command = if command_parts.is_empty() {
-                String::new()
-            } else {
-                command_parts.join(" ")
+        for (name, cmd_snippet) in plugin.commands {
+            // The shell of the snippet takes precedence over the global one
+            let shell = cmd_snippet
+                .shell
+                .as_ref()
+                .or(config.shell.as_ref())
+                .map(Shell::from_config)
+                .unwrap_or_default();
+            let complete_command = match cmd_snippet.script {
+                Some(ref script) => script.clone(),
+                None => build_complete_command(
+                    &cmd_snippet.executable,
+                    &cmd_snippet.entry_point,
+                    &cmd_snippet.args,
+                    cmd_snippet.if_shell,
+                    &shell,
+                ),
             };
 
             all_commands.commands.push(FullCommandSnippet {
+                id: qualified_id(&plugin.name, &name),
                 name,
+                plugin_name: plugin.name.clone(),
                 complete_command,
                 config_file: plugin.config_file.clone(),
+                scope: plugin.scope,
+                shadows: None,
+                disabled: disabled.clone(),
                 labels: cmd_snippet.labels,
                 description: cmd_snippet.description,
                 executable: cmd_snippet.executable,
                 entry_point: cmd_snippet.entry_point,
                 args: cmd_snippet.args,
                 if_shell: cmd_snippet.if_shell,
+                placeholders: cmd_snippet.placeholders,
+                cwd: cmd_snippet.cwd,
+                env: cmd_snippet.env,
+                env_clear: cmd_snippet.env_clear,
+                env_file: cmd_snippet.env_file,
+                shell,
+                script: cmd_snippet.script,
+                interpreter: cmd_snippet.interpreter,
+                workflow: cmd_snippet.steps,
+                steps: Vec::new(),
+                on_failure: cmd_snippet.on_failure.unwrap_or_default(),
             });
         }
     }
 
+    shadow_system_commands(&mut all_commands);
+    warn_name_conflicts(&all_commands.commands);
+
+    // Keep a stable order independent of the hash map iteration order
+    all_commands.commands.sort_by(|a, b| {
+        (a.scope, &a.plugin_name, &a.name).cmp(&(b.scope, &b.plugin_name, &b.name))
+    });
+
+    // Steps may reference commands of any plugin, so resolve once all are loaded
+    workflow::resolve_workflows(&mut all_commands);
+
     Ok(all_commands)
 }
 
-/// Find all commands and perform operations
-pub fn find_command(edit: bool) -> Result<(), Box<dyn std::error::Error>> {
-    let config = load_config()?;
-    let commands = load_commands()?;
+/// Hide system commands that have the name of a user or project command
+///
+/// Hidden commands move to `shadowed`, out of the picker and plain name lookup.
+fn shadow_system_commands(all_commands: &mut AllCommandSnippets) {
+    let overriding: HashSet<String> = all_commands
+        .commands
+        .iter()
+        .filter(|command| command.scope != Scope::System)
+        .map(|command| command.name.clone())
+        .collect();
 
-    if commands.commands.is_empty() {
-        println!("No commands found");
-        return Ok(());
+    let (hidden, commands): (Vec<_>, Vec<_>) = all_commands
+        .commands
+        .drain(..)
+        .partition(|command| command.scope == Scope::System && overriding.contains(&command.name));
+    all_commands.commands = commands;
+    // Name of each hidden command with its id
+    let shadowed: HashMap<String, String> = hidden
+        .iter()
+        .map(|command| (command.name.clone(), command.id.clone()))
+        .collect();
+    all_commands.shadowed = hidden;
+
+    for command in all_commands.commands.iter_mut() {
+        if let Some(id) = shadowed.get(&command.name) {
+            diagnostics::verbose(format!(
+                "System command {} is shadowed by {}",
+                id, command.id
+            ));
+            command.shadows = Some(id.clone());
+        }
     }
+}
 
-    // Build fzf input
-    let mut fzf_input = Vec::new();
-    for (index, command) in commands.commands.iter().enumerate() {
-        let executable_str = command
-            .executable
-            .as_ref()
-            .map(|p| p.to_string_lossy().to_string())
-            .unwrap_or_else(|| "None".to_string());
-
-        let labels_str = if command.labels.is_empty() {
-            "No labels".to_string()
-        } else {
-            command.labels.join(" ")
-        };
-
-        let line = format!(
-            "{}: {}{}{}{}{}{}{}",
-            index,
-            executable_str,
-            FZF_DELIMITER,
-            command.name,
-            FZF_DELIMITER,
-            labels_str,
-            FZF_DELIMITER,
-            command.config_file.to_string_lossy()
-        );
-        fzf_input.push(line);
-    }
-
-    // Create fzf command, using path from configuration
-    let mut fzf_cmd = Command::new(&config.fzf_executable)
-        .arg(format!("--delimiter={}", FZF_DELIMITER))
-        .arg("--with-nth=1,2,3")
-        .arg("--border")
-        .arg("--cycle")
-        .arg("--prompt=Select a command 🔍: ")
-        .arg(format!(
-            "--preview={} --color=always -A 20 {{2}} {{4}}",
-            config.rg_executable.to_string_lossy()
-        ))
-        .arg("--preview-window=right:45%")
-        .arg("--bind=esc:abort,ctrl-c:abort")
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .spawn()?;
+/// Warn about names defined by several plugins, which need the qualified id
+/// to be run by name
+///
+/// Runs after shadowing, so system commands overridden on purpose are not reported.
+fn warn_name_conflicts(commands: &[FullCommandSnippet]) {
+    let mut ids: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for command in commands {
+        ids.entry(&command.name).or_default().push(&command.id);
+    }
 
-    // Write data to fzf
-    if let Some(stdin) = &mut fzf_cmd.stdin {
-        for line in &fzf_input {
-            use std::io::Write;
-            writeln!(stdin, "{}", line)?;
+    for (name, ids) in ids {
+        if ids.len() > 1 {
+            diagnostics::warn(format!(
+                "Command '{}' is defined by several plugins, refer to it as {}",
+                name,
+                ids.join(" or ")
+            ));
         }
     }
+}
+
+/// Load all commands in the order shown by the picker, most relevant first
+pub fn load_sorted_commands(config: &YkConfig) -> Result<AllCommandSnippets> {
+    let mut commands = load_commands(config)?;
+    history::sort_by_frecency(&mut commands.commands, &history::load(&get_history_file()));
+    Ok(commands)
+}
 
-    // Get fzf output
-    let output = fzf_cmd.wait_with_output()?;
+/// Find all commands and perform operations
+///
+/// Returns the exit status of the executed command, if any. With `multi`
+/// several commands can be selected and are executed in order.
+pub fn find_command(edit: bool, multi: bool, keep_going: bool) -> Result<Option<ExitStatus>> {
+    let config = load_config()?;
+    let commands = load_sorted_commands(&config)?;
 
-    if !output.status.success() {
-        println!("User cancelled selection or fzf execution failed");
-        return Ok(());
+    if commands.commands.is_empty() {
+        println!("No commands found");
+        return Ok(None);
     }
 
-    let selected_line = String::from_utf8_lossy(&output.stdout);
-    let selected_line = selected_line.trim();
+    // Build picker items
+    let items: Vec<PickerItem> = commands
+        .commands
+        .iter()
+        .map(|command| {
+            let executable_str = if command.is_workflow() {
+                "workflow".to_string()
+            } else if command.is_script() {
+                command.script_interpreter().to_string_lossy().to_string()
+            } else {
+                command
+                    .executable
+                    .as_ref()
+                    .map(|p| p.to_string_lossy().to_string())
+                    .unwrap_or_else(|| "None".to_string())
+            };
 
-    if selected_line.is_empty() {
-        println!("No command selected");
-        return Ok(());
-    }
+            // Commands hiding a system command are marked
+            let scope_str = match command.shadows {
+                Some(_) => format!("{} (overrides system)", command.scope.name()),
+                None => command.scope.name().to_string(),
+            };
 
-    // Parse selected command index
-    let parts: Vec<&str> = selected_line.split(FZF_DELIMITER).collect();
+            let labels_str = if command.labels.is_empty() {
+                "No labels".to_string()
+            } else {
+                command.labels.join(" ")
+            };
 
-    if parts.is_empty() {
-        println!("Invalid selection format: {}", selected_line);
-        return Ok(());
-    }
+            let description = command.description.clone().unwrap_or_default();
+            let description = match command.disabled {
+                Some(ref reason) => format!("(disabled: {}) {}", reason, description),
+                None => description,
+            };
+
+            PickerItem {
+                fields: vec![
+                    scope_str,
+                    executable_str,
+                    command.id.clone(),
+                    labels_str,
+                    description,
+                ],
+                hidden: vec![command.id.clone()],
+            }
+        })
+        .collect();
 
-    // Extract index - first part should be "index: executable"
-    let first_part = parts[0];
-    let index_str = first_part.split(':').next().unwrap_or("").trim();
+    // fzf runs `yk preview` with the id of the highlighted command
+    let preview = |index: usize| preview::render_preview(&commands.commands[index], false);
+    let preview_command = env::current_exe()
+        .map(|exe| {
+            // The preview runs in another process, which must use the same directory
+            let shell = Shell::default();
+            format!(
+                "{} --quiet --config-dir {} preview {{6}}",
+                shell.quote(&exe.to_string_lossy()),
+                shell.quote(&get_config_dir().to_string_lossy())
+            )
+        })
+        .ok();
+    let options = PickOptions {
+        prompt: "Select a command 🔍: ".to_string(),
+        preview_command,
+        preview: Some(&preview),
+        multi,
+        ..Default::default()
+    };
 
-    let index: usize = match index_str.parse::<usize>() {
-        Ok(num) if num < commands.commands.len() => num,
-        Ok(num) => {
-            println!(
-                "Index {} out of range, valid range is 0-{}, total commands: {}",
-                num,
-                commands.commands.len() - 1,
-                commands.commands.len()
-            );
-            return Ok(());
-        }
-        Err(e) => {
-            println!(
-                "Failed to parse index: {}, original value: '{}'",
-                e, index_str
-            );
-            return Ok(());
-        }
+    let picker = create_picker(config.picker, &config.fzf_executable);
+    let Some(indices) = picker.pick(&items, &options)? else {
+        println!("User cancelled selection");
+        return Ok(None);
     };
 
-    let selected_command = &commands.commands[index];
+    let selected_commands: Vec<&FullCommandSnippet> = indices
+        .iter()
+        .map(|&index| &commands.commands[index])
+        .collect();
 
     // If edit parameter is enabled, open editor
     if edit {
-        println!("Editing command: {}", selected_command.name);
-        println!("Current command: {}", selected_command.complete_command);
+        let mut config_paths: Vec<&PathBuf> = Vec::new();
+        for selected_command in &selected_commands {
+            println!("Editing command: {}", selected_command.name);
+            println!("Current command: {}", selected_command.complete_command);
+
+            let config_path = &selected_command.config_file;
+            if !config_path.exists() {
+                println!("Configuration file does not exist: {:?}", config_path);
+            } else if !config_paths.contains(&config_path) {
+                config_paths.push(config_path);
+            }
+        }
 
-        let config_path = &selected_command.config_file;
-        if config_path.exists() {
-            println!("Opening configuration file: {:?}", config_path);
+        if !config_paths.is_empty() {
+            println!("Opening configuration file: {:?}", config_paths);
             let status = std::process::Command::new(&config.editor)
-                .arg(config_path)
-                .status()?;
+                .args(&config_paths)
+                .status()
+                .map_err(|e| {
+                    YkError::Execution(format!("Failed to start editor {:?}: {}", config.editor, e))
+                })?;
 
             if status.success() {
                 println!("Configuration file opened, please edit and save");
             } else {
                 println!("Failed to open configuration file");
             }
-        } else {
-            println!("Configuration file does not exist: {:?}", config_path);
         }
-        return Ok(());
+        return Ok(None);
     }
 
-    // Copy to clipboard
+    // Fill in placeholders
+    let mut filled_commands = Vec::new();
+    for selected_command in selected_commands {
+        // Choices of placeholders may run commands of the project already
+        selected_command.ensure_enabled()?;
+        trust::ensure_trusted(selected_command)?;
+        let values = prompt_placeholders(selected_command, &HashMap::new(), picker.as_ref())?;
+        filled_commands.push((selected_command.fill_placeholders(&values), values));
+    }
+
+    // Copy to clipboard, several commands are joined by newlines
     if config.if_yank {
+        let complete_commands: Vec<&str> = filled_commands
+            .iter()
+            .map(|(command, _)| command.complete_command.as_str())
+            .collect();
+        let contents = complete_commands.join("\n");
+
         let mut ctx: ClipboardContext = ClipboardProvider::new()
-            .map_err(|e| format!("Clipboard initialization failed: {}", e))?;
+            .map_err(|e| YkError::Clipboard(format!("Clipboard initialization failed: {}", e)))?;
 
-        ctx.set_contents(selected_command.complete_command.clone())
-            .map_err(|e| format!("Failed to copy to clipboard: {}", e))?;
+        ctx.set_contents(contents.clone())
+            .map_err(|e| YkError::Clipboard(format!("Failed to copy to clipboard: {}", e)))?;
 
-        println!("Copied to clipboard: {}", selected_command.complete_command);
+        println!("Copied to clipboard: {}", contents);
+
+        if !config.if_run {
+            for (command, values) in &filled_commands {
+                record_history(&HistoryEntry::new(
+                    command,
+                    HistoryAction::Yank,
+                    values,
+                    &[],
+                ));
+            }
+        }
     }
 
     // Execute command
     if config.if_run {
         if config.if_run_confirm {
-            print!(
-                "Confirm to run command: {} (y/N): ",
-                selected_command.complete_command
-            );
-            io::stdout().flush()?;
-
-            let mut confirm = String::new();
-            io::stdin().read_line(&mut confirm)?;
-
-            if confirm.trim().to_lowercase() != "y" {
-                println!("Execution cancelled");
-                return Ok(());
+            let commands: Vec<_> = filled_commands.iter().map(|(command, _)| command).collect();
+            if !confirm_run(&commands)? {
+                return Ok(None);
             }
         }
 
         // Execute command
-        if selected_command.if_shell {
-            let status = if cfg!(target_os = "windows") {
-                std::process::Command::new("cmd")
-                    .arg("/C")
-                    .arg(&selected_command.complete_command)
-                    .status()?
-            } else {
-                std::process::Command::new("sh")
-                    .arg("-c")
-                    .arg(&selected_command.complete_command)
-                    .status()?
-            };
+        if let [(command, values)] = filled_commands.as_slice() {
+            return execute_and_record(command, values, &[]);
+        }
+        return execute_sequence(&filled_commands, keep_going);
+    }
 
-            if !status.success() {
-                println!("Command execution failed");
+    Ok(None)
+}
+
+/// Execute several commands in order and print a summary of their exit codes
+///
+/// Execution stops at the first failure unless `keep_going` is set. Returns
+/// the status of the first failed command, or of the last one if all succeeded.
+pub fn execute_sequence(
+    commands: &[(FullCommandSnippet, HashMap<String, String>)],
+    keep_going: bool,
+) -> Result<Option<ExitStatus>> {
+    // `None` for a command that could not be started
+    let mut results: Vec<Option<Option<ExitStatus>>> = Vec::new();
+    let mut first_failure = None;
+    let mut last_status = None;
+
+    for (command, values) in commands {
+        println!("==> {}", command.complete_command);
+        let status = match execute_and_record(command, values, &[]) {
+            Ok(status) => {
+                results.push(Some(status));
+                status
             }
-        } else {
-            let mut parts = selected_command.complete_command.split_whitespace();
-            if let Some(program) = parts.next() {
-                let args: Vec<&str> = parts.collect();
-                let status = std::process::Command::new(program).args(&args).status()?;
+            Err(e) => {
+                diagnostics::error(e);
+                results.push(None);
+                Some(not_started_status())
+            }
+        };
 
-                if !status.success() {
-                    println!("Command execution failed");
+        if let Some(status) = status {
+            last_status = Some(status);
+            if !status.success() {
+                first_failure.get_or_insert(status);
+                if !keep_going {
+                    break;
                 }
             }
         }
     }
 
+    println!();
+    println!("Summary:");
+    for (position, (command, _)) in commands.iter().enumerate() {
+        let result = match results.get(position) {
+            None => "skipped".to_string(),
+            Some(None) => "not started".to_string(),
+            Some(Some(None)) => "nothing to run".to_string(),
+            Some(Some(Some(status))) => match status.code() {
+                Some(code) => format!("exit {}", code),
+                None => "killed".to_string(),
+            },
+        };
+        println!("  {:<16} {}", result, command.id);
+    }
+
+    Ok(first_failure.or(last_status))
+}
+
+/// Append a record to the execution history
+///
+/// Failing to record must not fail the command itself, so errors are only reported.
+/// Ask whether to run the commands, listing them first when there are several
+fn confirm_run(commands: &[&FullCommandSnippet]) -> Result<bool> {
+    if let [command] = commands {
+        print!(
+            "Confirm to run command: {} (y/N): ",
+            command.complete_command
+        );
+    } else {
+        println!("Commands to run:");
+        for (position, command) in commands.iter().enumerate() {
+            println!("  {}. {}", position + 1, command.complete_command);
+        }
+        print!("Confirm to run {} commands (y/N): ", commands.len());
+    }
+    io::stdout().flush()?;
+
+    let mut confirm = String::new();
+    io::stdin().read_line(&mut confirm)?;
+
+    if confirm.trim().to_lowercase() != "y" {
+        println!("Execution cancelled");
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+fn record_history(entry: &HistoryEntry) {
+    if let Err(e) = history::append(&get_history_file(), entry) {
+        diagnostics::warn(format!("failed to record history: {}", e));
+    }
+}
+
+/// Execute a command snippet and record it in the history
+pub fn execute_and_record(
+    command: &FullCommandSnippet,
+    values: &HashMap<String, String>,
+    extra_args: &[String],
+) -> Result<Option<ExitStatus>> {
+    let mut entry = HistoryEntry::new(command, HistoryAction::Run, values, extra_args);
+    let started = Instant::now();
+
+    let status = execute_command(command)?;
+    if let Some(status) = status {
+        entry.exit_code = status.code();
+        entry.duration_ms = Some(started.elapsed().as_millis() as u64);
+        record_history(&entry);
+    }
+
+    Ok(status)
+}
+
+/// Whether a child command is currently running in the foreground
+static CHILD_RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// Install the Ctrl-C handler
+///
+/// The terminal delivers Ctrl-C to the whole process group, so while a child
+/// runs yk ignores it and lets the child decide how to react. Otherwise yk
+/// terminates as if it had no handler.
+fn install_interrupt_handler() {
+    let _ = ctrlc::set_handler(|| {
+        if !CHILD_RUNNING.load(Ordering::SeqCst) {
+            #[cfg(unix)]
+            unsafe {
+                libc::signal(libc::SIGINT, libc::SIG_DFL);
+                libc::raise(libc::SIGINT);
+            }
+            std::process::exit(130);
+        }
+    });
+}
+
+/// Run a command in the foreground and wait for it to finish
+fn run_foreground(command: &mut Command) -> io::Result<ExitStatus> {
+    CHILD_RUNNING.store(true, Ordering::SeqCst);
+    let status = command.status();
+    CHILD_RUNNING.store(false, Ordering::SeqCst);
+    status
+}
+
+/// Exit yk with the same status as a finished child
+///
+/// On Unix a child killed by a signal makes yk re-raise that signal, so
+/// callers observe the same termination reason.
+pub fn exit_with_status(status: ExitStatus) -> ! {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+
+        if let Some(signal) = status.signal() {
+            let _ = io::stdout().flush();
+            unsafe {
+                libc::signal(signal, libc::SIG_DFL);
+                libc::raise(signal);
+            }
+            std::process::exit(128 + signal);
+        }
+    }
+
+    std::process::exit(status.code().unwrap_or(1))
+}
+
+/// Status of a command that could not be started, 127 like a shell reports it
+pub fn not_started_status() -> ExitStatus {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        ExitStatus::from_raw(127 << 8)
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::ExitStatusExt;
+        ExitStatus::from_raw(127)
+    }
+}
+
+/// Apply working directory and environment variables of a snippet
+pub fn apply_environment(process: &mut Command, command: &FullCommandSnippet) -> Result<()> {
+    if let Some(ref cwd) = command.cwd {
+        if !cwd.is_dir() {
+            return Err(YkError::Execution(format!(
+                "Working directory {:?} does not exist",
+                cwd
+            )));
+        }
+        process.current_dir(cwd);
+    }
+
+    if command.env_clear {
+        process.env_clear();
+    }
+
+    // Variables of the env file are overridden by `env`
+    if let Some(ref env_file) = command.env_file {
+        process.envs(dotenv::load(env_file)?);
+    }
+    process.envs(&command.env);
+
+    Ok(())
+}
+
+/// Execute a command snippet and wait for it to finish
+///
+/// Returns `None` if the snippet has nothing to execute.
+pub fn execute_command(command: &FullCommandSnippet) -> Result<Option<ExitStatus>> {
+    if command.is_workflow() {
+        return workflow::execute_workflow(command);
+    }
+
+    // Kept until the script has finished
+    let mut script_file = None;
+    let (program, mut process) = if let Some(ref script) = command.script {
+        let interpreter = command.script_interpreter();
+        let file = script::ScriptFile::create(script, interpreter)?;
+        let process = file.command(interpreter, &command.args);
+        script_file = Some(file);
+        (interpreter.to_string_lossy().to_string(), process)
+    } else if command.if_shell {
+        if command.complete_command.trim().is_empty() {
+            return Ok(None);
+        }
+
+        (
+            command.shell.program.to_string_lossy().to_string(),
+            command.shell.command(&command.complete_command),
+        )
+    } else {
+        let argv = command.argv();
+        let Some((program, args)) = argv.split_first() else {
+            return Ok(None);
+        };
+
+        let mut process = Command::new(program);
+        process.args(args);
+        (program.clone(), process)
+    };
+
+    apply_environment(&mut process, command)?;
+    let status = run_foreground(&mut process)
+        .map_err(|e| YkError::Execution(format!("Failed to run {:?}: {}", program, e)))?;
+    drop(script_file);
+    Ok(Some(status))
+}
+
+/// Separator of the plugin name and the command name in a qualified id
+pub const ID_SEPARATOR: char = ':';
+
+/// Qualified id of a command, e.g. `ops:deploy`
+pub fn qualified_id(plugin_name: &str, name: &str) -> String {
+    format!("{}{}{}", plugin_name, ID_SEPARATOR, name)
+}
+
+/// Plugin and command name of a qualified id
+///
+/// `plugin/name`, the form used before qualified ids, is accepted as well.
+fn split_qualified_id(id: &str) -> Option<(&str, &str)> {
+    id.split_once(ID_SEPARATOR).or_else(|| id.split_once('/'))
+}
+
+/// Find a command by name or by qualified id
+///
+/// Names are matched first, so names containing `:` or `/` still work.
+/// Shadowed system commands are only found by their qualified id.
+pub fn find_command_by_name<'a>(
+    commands: &'a AllCommandSnippets,
+    name: &str,
+) -> Result<&'a FullCommandSnippet> {
+    let mut matches: Vec<&FullCommandSnippet> = commands
+        .commands
+        .iter()
+        .filter(|command| command.name == name)
+        .collect();
+
+    if matches.is_empty() {
+        if let Some((plugin_name, command_name)) = split_qualified_id(name) {
+            matches = commands
+                .commands
+                .iter()
+                .chain(&commands.shadowed)
+                .filter(|command| {
+                    command.plugin_name == plugin_name && command.name == command_name
+                })
+                .collect();
+        }
+    }
+
+    match matches.len() {
+        0 => Err(YkError::Execution(format!("Command '{}' not found", name))),
+        1 => Ok(matches[0]),
+        _ => {
+            let candidates: Vec<&str> = matches.iter().map(|command| command.id.as_str()).collect();
+            Err(YkError::Execution(format!(
+                "Command '{}' is ambiguous, use one of: {}",
+                name,
+                candidates.join(", ")
+            )))
+        }
+    }
+}
+
+/// Run a command by name without the interactive finder
+///
+/// Returns the exit status of the executed command.
+pub fn run_command(name: &str, set: &[String], extra_args: &[String]) -> Result<ExitStatus> {
+    let config = load_config()?;
+    let commands = load_commands(&config)?;
+    let command = find_command_by_name(&commands, name)?;
+    if command.is_workflow() && !extra_args.is_empty() {
+        return Err(YkError::Execution(format!(
+            "Workflow '{}' does not accept extra arguments",
+            name
+        )));
+    }
+    command.ensure_enabled()?;
+    trust::ensure_trusted(command)?;
+
+    // Parse preset placeholder values
+    let mut preset = HashMap::new();
+    for item in set {
+        let (key, value) = item.split_once('=').ok_or_else(|| {
+            YkError::Execution(format!(
+                "Invalid placeholder value '{}', expected NAME=VALUE",
+                item
+            ))
+        })?;
+        preset.insert(key.to_string(), value.to_string());
+    }
+
+    let values = prompt_placeholders(
+        command,
+        &preset,
+        create_picker(config.picker, &config.fzf_executable).as_ref(),
+    )?;
+    let mut command = command.fill_placeholders(&values);
+    command.append_args(extra_args);
+
+    match execute_and_record(&command, &values, extra_args)? {
+        Some(status) => Ok(status),
+        None => Err(YkError::Execution(format!(
+            "Command '{}' has nothing to execute",
+            name
+        ))),
+    }
+}
+
+/// First line of a recorded command for the one-line listing
+///
+/// Workflows and scripts span several lines, `...` marks that the command
+/// continues. Control characters are escaped so they cannot garble the terminal.
+fn history_line(command: &str) -> String {
+    let mut lines = command.trim().lines();
+    let mut line: String = lines
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .flat_map(|c| {
+            if c.is_control() {
+                c.escape_default().collect::<Vec<_>>()
+            } else {
+                vec![c]
+            }
+        })
+        .collect();
+    if lines.next().is_some() {
+        line.push_str(" ...");
+    }
+    line
+}
+
+/// List, search and re-run past invocations
+///
+/// Entries are numbered from the most recent one, starting at 1.
+pub fn history_command(
+    query: Option<&str>,
+    limit: usize,
+    rerun: Option<usize>,
+) -> Result<Option<ExitStatus>> {
+    let entries = history::load(&get_history_file());
+
+    if let Some(number) = rerun {
+        let entry = number
+            .checked_sub(1)
+            .and_then(|index| entries.iter().rev().nth(index))
+            .ok_or_else(|| {
+                YkError::Execution(format!("History entry {} does not exist", number))
+            })?;
+        return rerun_history_entry(entry);
+    }
+
+    let query = query.map(|q| q.to_lowercase());
+    let mut shown = 0;
+    for (index, entry) in entries.iter().rev().enumerate() {
+        if shown >= limit {
+            break;
+        }
+        if let Some(ref query) = query {
+            let haystack = format!(
+                "{} {}",
+                qualified_id(&entry.plugin_name, &entry.name),
+                entry.command
+            );
+            if !haystack.to_lowercase().contains(query) {
+                continue;
+            }
+        }
+
+        let status = match (entry.action, entry.exit_code) {
+            (HistoryAction::Yank, _) => "yank".to_string(),
+            (HistoryAction::Run, Some(code)) => format!("exit {}", code),
+            (HistoryAction::Run, None) => "killed".to_string(),
+        };
+        let duration = entry
+            .duration_ms
+            .map(|ms| format!("{:.1}s", ms as f64 / 1000.0))
+            .unwrap_or_default();
+
+        println!(
+            "{:>4}  {:>8}  {:<8} {:>7}  {}  {}",
+            index + 1,
+            history::format_age(entry.timestamp),
+            status,
+            duration,
+            qualified_id(&entry.plugin_name, &entry.name),
+            history_line(&entry.command)
+        );
+        shown += 1;
+    }
+
+    if shown == 0 {
+        println!("No history found");
+    }
+
+    Ok(None)
+}
+
+/// Run a history entry again with the same placeholder values and extra arguments
+fn rerun_history_entry(entry: &HistoryEntry) -> Result<Option<ExitStatus>> {
+    let config = load_config()?;
+    let commands = load_commands(&config)?;
+
+    let command = match commands
+        .commands
+        .iter()
+        .find(|command| entry.matches(command))
+    {
+        Some(command) => command,
+        None => find_command_by_name(&commands, &qualified_id(&entry.plugin_name, &entry.name))?,
+    };
+
+    command.ensure_enabled()?;
+    trust::ensure_trusted(command)?;
+
+    // Placeholders added since the entry was recorded are asked for
+    let values = prompt_placeholders(
+        command,
+        &entry.values,
+        create_picker(config.picker, &config.fzf_executable).as_ref(),
+    )?;
+    let mut command = command.fill_placeholders(&values);
+    command.append_args(&entry.extra_args);
+
+    if config.if_run_confirm && !confirm_run(&[&command])? {
+        return Ok(None);
+    }
+
+    execute_and_record(&command, &values, &entry.extra_args)
+}
+
+/// Print the preview of a command, referenced by picker index or by name
+pub fn preview_command(id: &str) -> Result<()> {
+    generator::use_cache_only();
+    let config = load_config()?;
+    let commands = load_sorted_commands(&config)?;
+
+    let command = match id.parse::<usize>() {
+        Ok(index) => commands
+            .commands
+            .get(index)
+            .ok_or_else(|| YkError::Execution(format!("Index {} out of range", index)))?,
+        Err(_) => find_command_by_name(&commands, id)?,
+    };
+
+    // The picker may close the pipe before the preview is fully written
+    let _ = writeln!(io::stdout(), "{}", preview::render_preview(command, true));
     Ok(())
 }
 
@@ -654,6 +1774,15 @@ pub fn find_command(edit: bool) -> Result<(), Box<dyn std::error::Error>> {
 #[command(name = "yk")]
 #[command(about = "Simple commands and complex commands plugin management tool")]
 struct Cli {
+    /// Print details about loaded configuration files
+    #[arg(short, long, global = true, conflicts_with = "quiet")]
+    verbose: bool,
+    /// Do not print warnings
+    #[arg(short, long, global = true)]
+    quiet: bool,
+    /// Configuration directory, overrides YK_CONFIG_DIR
+    #[arg(long, global = true, value_name = "DIR")]
+    config_dir: Option<PathBuf>,
     #[command(subcommand)]
     command: Option<Commands>,
 }
@@ -668,20 +1797,235 @@ enum Commands {
         /// Edit command configuration file
         #[arg(short, long)]
         edit: bool,
+        /// Select several commands and run them in order
+        #[arg(short, long)]
+        multi: bool,
+        /// Continue with the next command when one fails
+        #[arg(short, long, requires = "multi")]
+        keep_going: bool,
     },
     /// Create new simple command
     New,
+    /// Run a command by name without the interactive finder
+    Run {
+        /// Command name, or `plugin:name` to pick a plugin command
+        name: String,
+        /// Placeholder value, can be repeated
+        #[arg(short, long, value_name = "NAME=VALUE")]
+        set: Vec<String>,
+        /// Extra arguments appended to the command
+        #[arg(last = true)]
+        args: Vec<String>,
+    },
+    /// Check the configuration, required programs and all snippet files
+    Doctor,
+    /// Install, update, enable, disable, remove and list plugins
+    Plugin {
+        #[command(subcommand)]
+        action: plugin::PluginAction,
+    },
+    /// Allow the snippets of the current project to run
+    Trust {
+        /// Withdraw the approval instead
+        #[arg(long)]
+        revoke: bool,
+    },
+    /// Print the JSON Schema of a configuration file
+    Schema {
+        /// File the schema describes
+        #[arg(value_enum)]
+        kind: SchemaKind,
+    },
+    /// Print the preview of a command, used by the picker
+    #[command(hide = true)]
+    Preview {
+        /// Picker index, command name or `plugin:name`
+        id: String,
+    },
+    /// List, search and re-run past invocations
+    History {
+        /// Only show entries whose command contains this text
+        query: Option<String>,
+        /// Maximum number of entries to show
+        #[arg(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+        /// Re-run the entry with this number
+        #[arg(short, long, value_name = "NUMBER")]
+        rerun: Option<usize>,
+    },
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
+fn main() {
     let cli = Cli::parse();
+    diagnostics::set_verbosity(if cli.verbose {
+        Verbosity::Verbose
+    } else if cli.quiet {
+        Verbosity::Quiet
+    } else {
+        Verbosity::Normal
+    });
+    install_interrupt_handler();
+
+    // Printing a schema is the only command that works without a configuration directory
+    let config_dir = match cli.config_dir {
+        Some(dir) => Ok(Some(dir)),
+        None if matches!(cli.command, Some(Commands::Schema { .. })) => Ok(None),
+        None => resolve_config_dir(env_path).map(Some),
+    };
+    let status = config_dir.and_then(|dir| {
+        if let Some(dir) = dir {
+            set_config_dir(dir);
+        }
+        match cli.command {
+            Some(Commands::Init) => init_config().map(|_| None), // Initialize configuration file
+            Some(Commands::New) => new_command().map(|_| None),  // Create new command
+            Some(Commands::Find {
+                edit,
+                multi,
+                keep_going,
+            }) => find_command(edit, multi, keep_going), // Find and execute commands
+            Some(Commands::Run { name, set, args }) => run_command(&name, &set, &args).map(Some), // Run command by name
+            Some(Commands::History {
+                query,
+                limit,
+                rerun,
+            }) => history_command(query.as_deref(), limit, rerun), // Show or re-run history
+            Some(Commands::Schema { kind }) => {
+                let _ = writeln!(io::stdout(), "{}", schema::schema_json(kind)); // Print JSON schema
+                Ok(None)
+            }
+            Some(Commands::Doctor) => doctor::doctor().map(|_| None), // Validate setup
+            Some(Commands::Plugin { action }) => plugin::plugin_command(action).map(|_| None), // Manage plugins
+            Some(Commands::Trust { revoke }) => trust::trust_command(revoke).map(|_| None), // Trust project snippets
+            Some(Commands::Preview { id }) => preview_command(&id).map(|_| None), // Render command preview
+            None => find_command(false, false, false), // Default execute find command
+        }
+    });
 
-    match cli.command {
-        Some(Commands::Init) => init_config()?, // Initialize configuration file
-        Some(Commands::New) => new_command()?,  // Create new command
-        Some(Commands::Find { edit }) => find_command(edit)?, // Find and execute commands
-        None => find_command(false)?,           // Default execute find command
+    match status {
+        // Exit with the status of the executed command
+        Ok(Some(status)) => exit_with_status(status),
+        Ok(None) => {}
+        Err(e) => {
+            diagnostics::error(e);
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Lookup of environment variables from a fixed list
+    fn vars(pairs: &[(&str, &Path)]) -> impl Fn(&str) -> Option<PathBuf> {
+        let vars: HashMap<String, PathBuf> = pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_path_buf()))
+            .collect();
+        move |name| vars.get(name).cloned()
     }
 
-    Ok(())
+    #[test]
+    fn resolve_config_dir_prefers_the_override() {
+        let dir = resolve_config_dir(vars(&[
+            (CONFIG_DIR_ENV, Path::new("/srv/yk")),
+            ("HOME", Path::new("/home/me")),
+        ]))
+        .unwrap();
+        assert_eq!(dir, Path::new("/srv/yk"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn resolve_config_dir_follows_xdg() {
+        let temp = tempfile::tempdir().unwrap();
+        let home = temp.path().join("home");
+        let xdg = temp.path().join("xdg");
+
+        let dir = resolve_config_dir(vars(&[("HOME", &home), ("XDG_CONFIG_HOME", &xdg)])).unwrap();
+        assert_eq!(dir, xdg.join("yk"));
+
+        // Relative values are ignored
+        let dir = resolve_config_dir(vars(&[
+            ("HOME", &home),
+            ("XDG_CONFIG_HOME", Path::new("relative")),
+        ]))
+        .unwrap();
+        assert_eq!(dir, home.join(".config").join("yk"));
+
+        // An existing legacy directory is kept until the XDG one is created
+        fs::create_dir_all(home.join(".config").join("yk")).unwrap();
+        let dir = resolve_config_dir(vars(&[("HOME", &home), ("XDG_CONFIG_HOME", &xdg)])).unwrap();
+        assert_eq!(dir, home.join(".config").join("yk"));
+        fs::create_dir_all(xdg.join("yk")).unwrap();
+        let dir = resolve_config_dir(vars(&[("HOME", &home), ("XDG_CONFIG_HOME", &xdg)])).unwrap();
+        assert_eq!(dir, xdg.join("yk"));
+    }
+
+    #[test]
+    fn resolve_config_dir_fails_without_a_home_directory() {
+        assert!(resolve_config_dir(vars(&[])).is_err());
+    }
+
+    fn snippet(plugin_name: &str, name: &str, scope: Scope) -> FullCommandSnippet {
+        FullCommandSnippet {
+            id: qualified_id(plugin_name, name),
+            name: name.to_string(),
+            plugin_name: plugin_name.to_string(),
+            scope,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn find_command_by_name_requires_qualified_ids_for_conflicts() {
+        let mut commands = AllCommandSnippets::new();
+        commands.commands = vec![
+            snippet("ops", "deploy", Scope::User),
+            snippet("web", "deploy", Scope::User),
+            snippet("ops", "build", Scope::User),
+        ];
+
+        assert_eq!(
+            find_command_by_name(&commands, "build").unwrap().id,
+            "ops:build"
+        );
+        assert_eq!(
+            find_command_by_name(&commands, "web:deploy").unwrap().id,
+            "web:deploy"
+        );
+        // The form used before qualified ids
+        assert_eq!(
+            find_command_by_name(&commands, "web/deploy").unwrap().id,
+            "web:deploy"
+        );
+
+        let error = find_command_by_name(&commands, "deploy")
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("ops:deploy, web:deploy"), "{}", error);
+        assert!(find_command_by_name(&commands, "web:build").is_err());
+    }
+
+    #[test]
+    fn shadowed_system_commands_are_reachable_by_qualified_id() {
+        let mut commands = AllCommandSnippets::new();
+        commands.commands = vec![
+            snippet("base", "deploy", Scope::System),
+            snippet("simple", "deploy", Scope::User),
+        ];
+        shadow_system_commands(&mut commands);
+
+        assert_eq!(commands.commands.len(), 1);
+        assert_eq!(commands.commands[0].shadows.as_deref(), Some("base:deploy"));
+        assert_eq!(
+            find_command_by_name(&commands, "deploy").unwrap().id,
+            "simple:deploy"
+        );
+        assert_eq!(
+            find_command_by_name(&commands, "base:deploy").unwrap().id,
+            "base:deploy"
+        );
+    }
 }
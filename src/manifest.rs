@@ -0,0 +1,162 @@
+use std::cmp::Ordering;
+use std::fs;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::error::Result;
+use crate::format;
+use crate::sources::PluginFile;
+
+/// Name of the manifest file in a plugin directory, in any supported format
+pub const MANIFEST_FILE_STEM: &str = "plugin";
+/// Key of the manifest section inside a snippet file
+pub const MANIFEST_KEY: &str = "$plugin";
+
+/// Metadata and requirements of a plugin
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct PluginManifest {
+    /// Display name, the directory name when not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Version of the plugin
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Executables that must be on `PATH`, commands are disabled otherwise
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub requires: Vec<String>,
+    /// Oldest yk version the plugin works with, e.g. `0.2.0`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_yk_version: Option<String>,
+    /// Not loaded unless listed in `enabled_plugins` of the configuration
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub disabled: bool,
+    /// Seconds the output of the generator is reused, overrides `generator_cache_ttl`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache_ttl: Option<u64>,
+}
+
+/// Snippet file read for its manifest section only
+#[derive(Deserialize)]
+struct ManifestSection {
+    #[serde(rename = "$plugin")]
+    plugin: Option<PluginManifest>,
+}
+
+/// Load the manifest of a plugin
+///
+/// A manifest file in the plugin directory takes precedence over a `$plugin`
+/// section in the snippet file. Snippet files outside a plugin directory, like
+/// the simple commands file, only have the section: a `plugin.json` next to
+/// them belongs to something else.
+pub fn load(plugin_file: &PluginFile) -> Result<Option<PluginManifest>> {
+    let config_file = &plugin_file.config_file;
+    let manifest_file = plugin_file
+        .plugin_dir
+        .as_deref()
+        .and_then(|plugin_dir| format::find_file(plugin_dir, MANIFEST_FILE_STEM))
+        // The snippet file of a plugin named `plugin` is not a manifest
+        .filter(|manifest_file| manifest_file != config_file);
+    if let Some(manifest_file) = manifest_file {
+        let content = fs::read_to_string(&manifest_file)?;
+        return format::parse_document(&manifest_file, &content).map(Some);
+    }
+
+    // Generators have no manifest section
+    if format::FileFormat::from_path(config_file).is_none() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(config_file)?;
+    if content.trim().is_empty() {
+        return Ok(None);
+    }
+    let section: Option<ManifestSection> = format::parse_document(config_file, &content)?;
+    Ok(section.and_then(|section| section.plugin))
+}
+
+/// Compare dotted version numbers, missing parts count as 0
+///
+/// Suffixes like `-beta` are ignored.
+fn compare_versions(a: &str, b: &str) -> Ordering {
+    let parse = |version: &str| -> Vec<u64> {
+        version
+            .trim_start_matches('v')
+            .split(['-', '+'])
+            .next()
+            .unwrap_or_default()
+            .split('.')
+            .map(|part| part.parse().unwrap_or(0))
+            .collect()
+    };
+    let (a, b) = (parse(a), parse(b));
+    let len = a.len().max(b.len());
+    let part = |parts: &[u64], index: usize| parts.get(index).copied().unwrap_or(0);
+    (0..len)
+        .map(|index| part(&a, index).cmp(&part(&b, index)))
+        .find(|ordering| ordering.is_ne())
+        .unwrap_or(Ordering::Equal)
+}
+
+impl PluginManifest {
+    /// Why the commands of the plugin cannot be used, if they cannot
+    pub fn unmet_requirement(&self) -> Option<String> {
+        if let Some(ref min_version) = self.min_yk_version {
+            let version = env!("CARGO_PKG_VERSION");
+            if compare_versions(version, min_version).is_lt() {
+                return Some(format!(
+                    "requires yk {} or later, this is {}",
+                    min_version, version
+                ));
+            }
+        }
+
+        let missing: Vec<&str> = self
+            .requires
+            .iter()
+            .filter(|program| which::which(program).is_err())
+            .map(String::as_str)
+            .collect();
+        if !missing.is_empty() {
+            return Some(format!("requires {} on PATH", missing.join(", ")));
+        }
+
+        None
+    }
+
+    /// One line summary, e.g. `1.2.0 by Jane: Deployment helpers`
+    pub fn summary(&self) -> String {
+        let mut summary = self.version.clone().unwrap_or_default();
+        if let Some(ref author) = self.author {
+            if !summary.is_empty() {
+                summary.push(' ');
+            }
+            summary.push_str(&format!("by {}", author));
+        }
+        if let Some(ref description) = self.description {
+            if !summary.is_empty() {
+                summary.push_str(": ");
+            }
+            summary.push_str(description);
+        }
+        summary
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compare_versions_compares_numerically() {
+        assert_eq!(compare_versions("0.10.0", "0.9.3"), Ordering::Greater);
+        assert_eq!(compare_versions("1.2", "1.2.0"), Ordering::Equal);
+        assert_eq!(compare_versions("v1.2.3", "1.2.3"), Ordering::Equal);
+        assert_eq!(compare_versions("1.2.3-beta", "1.2.3"), Ordering::Equal);
+        assert_eq!(compare_versions("1.2", "1.2.1"), Ordering::Less);
+    }
+}
@@ -0,0 +1,578 @@
+use std::cmp::Reverse;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::{Attribute, Print, SetAttribute};
+use crossterm::terminal::{self, ClearType};
+use crossterm::{cursor, queue};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Result, YkError};
+
+/// Delimiter between fields of fzf input lines
+const FZF_DELIMITER: &str = "🍃────🍃";
+
+/// Picker backend selection
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum PickerBackend {
+    /// fzf when it is installed, the builtin picker otherwise
+    #[default]
+    Auto,
+    Fzf,
+    Builtin,
+}
+
+/// Item offered by a picker
+#[derive(Debug, Clone, Default)]
+pub struct PickerItem {
+    /// Fields shown in the list and matched against the query
+    pub fields: Vec<String>,
+    /// Fields passed to fzf but neither shown nor matched, e.g. for preview commands
+    pub hidden: Vec<String>,
+}
+
+/// Options of a single pick
+#[derive(Clone, Default)]
+pub struct PickOptions<'a> {
+    pub prompt: String,
+    pub header: Option<String>,
+    /// Preview command run by fzf, fields are referenced as `{1}`, `{2}`...
+    pub preview_command: Option<String>,
+    /// Preview text used by the builtin picker
+    pub preview: Option<&'a dyn Fn(usize) -> String>,
+    /// Allow selecting several items
+    pub multi: bool,
+}
+
+/// Interactive selection from a list of items
+pub trait Picker {
+    /// Let the user pick items, returns their indices or `None` if cancelled
+    ///
+    /// Unless `multi` is set, at most one index is returned.
+    fn pick(&self, items: &[PickerItem], options: &PickOptions) -> Result<Option<Vec<usize>>>;
+
+    /// Let the user pick a single item, returns its index or `None` if cancelled
+    fn pick_one(&self, items: &[PickerItem], options: &PickOptions) -> Result<Option<usize>> {
+        let options = PickOptions {
+            multi: false,
+            ..options.clone()
+        };
+        Ok(self
+            .pick(items, &options)?
+            .and_then(|indices| indices.first().copied()))
+    }
+}
+
+/// Terminal and pipe failures while picking
+fn picker_error(e: io::Error) -> YkError {
+    YkError::Picker(e.to_string())
+}
+
+/// Create the picker for a backend
+///
+/// `Auto` falls back to the builtin picker when fzf cannot be found.
+pub fn create_picker(backend: PickerBackend, fzf_executable: &PathBuf) -> Box<dyn Picker> {
+    match backend {
+        PickerBackend::Fzf => Box::new(FzfPicker::new(fzf_executable.clone())),
+        PickerBackend::Builtin => Box::new(BuiltinPicker),
+        PickerBackend::Auto => {
+            if which::which(fzf_executable).is_ok() {
+                Box::new(FzfPicker::new(fzf_executable.clone()))
+            } else {
+                Box::new(BuiltinPicker)
+            }
+        }
+    }
+}
+
+/// Picker backed by an external fzf process
+pub struct FzfPicker {
+    executable: PathBuf,
+}
+
+impl FzfPicker {
+    pub fn new(executable: PathBuf) -> Self {
+        Self { executable }
+    }
+}
+
+impl Picker for FzfPicker {
+    fn pick(&self, items: &[PickerItem], options: &PickOptions) -> Result<Option<Vec<usize>>> {
+        let visible = items
+            .iter()
+            .map(|item| item.fields.len())
+            .max()
+            .unwrap_or(1);
+
+        let mut fzf_cmd = Command::new(&self.executable);
+        fzf_cmd
+            .arg(format!("--delimiter={}", FZF_DELIMITER))
+            .arg(format!("--with-nth=1..{}", visible))
+            .arg("--border")
+            .arg("--cycle")
+            .arg(format!("--prompt={}", options.prompt))
+            .arg("--bind=esc:abort,ctrl-c:abort")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped());
+        if let Some(ref header) = options.header {
+            fzf_cmd.arg(format!("--header={}", header));
+        }
+        if options.multi {
+            fzf_cmd.arg("--multi");
+        }
+        if let Some(ref preview_command) = options.preview_command {
+            fzf_cmd
+                .arg(format!("--preview={}", preview_command))
+                .arg("--preview-window=right:45%");
+        }
+        let mut fzf_cmd = fzf_cmd.spawn().map_err(|e| {
+            YkError::Picker(format!(
+                "Failed to start fzf {:?}: {}",
+                self.executable.to_string_lossy(),
+                e
+            ))
+        })?;
+
+        // Write data to fzf, the index prefix identifies the selected line
+        if let Some(stdin) = &mut fzf_cmd.stdin {
+            for (index, item) in items.iter().enumerate() {
+                let mut fields = item.fields.clone();
+                fields.resize(visible, String::new());
+                fields.extend(item.hidden.iter().cloned());
+                writeln!(stdin, "{}: {}", index, fields.join(FZF_DELIMITER))
+                    .map_err(picker_error)?;
+            }
+        }
+
+        let output = fzf_cmd.wait_with_output().map_err(picker_error)?;
+        if !output.status.success() {
+            return Ok(None);
+        }
+
+        // One selected line per item, in selection order
+        let mut indices = Vec::new();
+        for selected_line in String::from_utf8_lossy(&output.stdout).lines() {
+            let selected_line = selected_line.trim();
+            if selected_line.is_empty() {
+                continue;
+            }
+
+            let index_str = selected_line.split(':').next().unwrap_or("").trim();
+            match index_str.parse::<usize>() {
+                Ok(index) if index < items.len() => indices.push(index),
+                _ => {
+                    return Err(YkError::Picker(format!(
+                        "Invalid selection format: {}",
+                        selected_line
+                    )))
+                }
+            }
+        }
+
+        Ok(if indices.is_empty() {
+            None
+        } else {
+            Some(indices)
+        })
+    }
+}
+
+/// Score how well a query token matches a text, `None` if it does not match
+///
+/// All characters of the token must appear in order. Consecutive matches and
+/// matches at word starts score higher, gaps lower the score. Matching is
+/// case-insensitive unless the token contains uppercase characters.
+pub fn fuzzy_score(token: &str, text: &str) -> Option<i64> {
+    let case_sensitive = token.chars().any(char::is_uppercase);
+    let normalize = |c: char| {
+        if case_sensitive {
+            c
+        } else {
+            c.to_ascii_lowercase()
+        }
+    };
+
+    let text: Vec<char> = text.chars().collect();
+    let mut score = 0;
+    let mut position = 0;
+    let mut previous_match: Option<usize> = None;
+
+    for wanted in token.chars().map(normalize) {
+        let found = (position..text.len()).find(|&i| normalize(text[i]) == wanted)?;
+
+        score += 16;
+        if previous_match.is_some_and(|previous| previous + 1 == found) {
+            score += 8;
+        }
+        let at_word_start = found == 0 || !text[found - 1].is_alphanumeric();
+        if at_word_start {
+            score += 10;
+        }
+        if let Some(previous) = previous_match {
+            score -= (found - previous - 1).min(8) as i64;
+        }
+
+        previous_match = Some(found);
+        position = found + 1;
+    }
+
+    Some(score)
+}
+
+/// Score an item against a whitespace separated query
+///
+/// Every token has to match at least one field, the best field counts.
+pub fn score_item(query: &str, item: &PickerItem) -> Option<i64> {
+    let mut total = 0;
+    for token in query.split_whitespace() {
+        total += item
+            .fields
+            .iter()
+            .filter_map(|field| fuzzy_score(token, field))
+            .max()?;
+    }
+    Some(total)
+}
+
+/// Terminal state of the builtin picker, restored when dropped
+struct TerminalGuard;
+
+impl TerminalGuard {
+    fn enter() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        let mut stderr = io::stderr();
+        if let Err(e) = queue!(stderr, terminal::EnterAlternateScreen, cursor::Hide)
+            .and_then(|_| stderr.flush())
+        {
+            let _ = terminal::disable_raw_mode();
+            return Err(e);
+        }
+        Ok(Self)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let mut stderr = io::stderr();
+        let _ = queue!(stderr, cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = stderr.flush();
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// Cut a string to at most `width` characters
+fn truncate(text: &str, width: usize) -> String {
+    text.chars().take(width).collect()
+}
+
+/// State of the builtin picker
+struct BuiltinState<'a> {
+    items: &'a [PickerItem],
+    query: String,
+    /// Indices of matching items, best match first
+    matches: Vec<usize>,
+    /// Marked items in the order they were marked
+    marked: Vec<usize>,
+    cursor: usize,
+    offset: usize,
+}
+
+impl<'a> BuiltinState<'a> {
+    fn new(items: &'a [PickerItem]) -> Self {
+        let mut state = Self {
+            items,
+            query: String::new(),
+            matches: Vec::new(),
+            marked: Vec::new(),
+            cursor: 0,
+            offset: 0,
+        };
+        state.update_matches();
+        state
+    }
+
+    /// Re-filter items after the query changed
+    fn update_matches(&mut self) {
+        let mut scored: Vec<(i64, usize)> = self
+            .items
+            .iter()
+            .enumerate()
+            .filter_map(|(index, item)| score_item(&self.query, item).map(|score| (score, index)))
+            .collect();
+        // Stable sort keeps the original order for equal scores
+        scored.sort_by_key(|&(score, _)| Reverse(score));
+
+        self.matches = scored.into_iter().map(|(_, index)| index).collect();
+        self.cursor = 0;
+        self.offset = 0;
+    }
+
+    fn move_cursor(&mut self, delta: isize) {
+        if self.matches.is_empty() {
+            return;
+        }
+        let last = self.matches.len() as isize - 1;
+        self.cursor = (self.cursor as isize + delta).clamp(0, last) as usize;
+    }
+
+    fn selected(&self) -> Option<usize> {
+        self.matches.get(self.cursor).copied()
+    }
+
+    /// Toggle the mark of the item under the cursor
+    fn toggle_mark(&mut self) {
+        if let Some(index) = self.selected() {
+            match self.marked.iter().position(|&marked| marked == index) {
+                Some(position) => {
+                    self.marked.remove(position);
+                }
+                None => self.marked.push(index),
+            }
+        }
+    }
+
+    /// Marked items, or the item under the cursor when nothing is marked
+    fn result(&self) -> Option<Vec<usize>> {
+        if !self.marked.is_empty() {
+            return Some(self.marked.clone());
+        }
+        self.selected().map(|index| vec![index])
+    }
+
+    /// Handle a key press, returns the result once the pick is finished
+    fn handle_key(
+        &mut self,
+        key: KeyEvent,
+        page: usize,
+        multi: bool,
+    ) -> Option<Option<Vec<usize>>> {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Enter => return Some(self.result()),
+            KeyCode::Tab if multi => {
+                self.toggle_mark();
+                self.move_cursor(1);
+            }
+            KeyCode::BackTab if multi => {
+                self.toggle_mark();
+                self.move_cursor(-1);
+            }
+            KeyCode::Esc => return Some(None),
+            KeyCode::Char('c') | KeyCode::Char('g') if ctrl => return Some(None),
+            KeyCode::Up => self.move_cursor(-1),
+            KeyCode::Down => self.move_cursor(1),
+            KeyCode::Char('p') | KeyCode::Char('k') if ctrl => self.move_cursor(-1),
+            KeyCode::Char('n') | KeyCode::Char('j') if ctrl => self.move_cursor(1),
+            KeyCode::PageUp => self.move_cursor(-(page as isize)),
+            KeyCode::PageDown => self.move_cursor(page as isize),
+            KeyCode::Home => self.cursor = 0,
+            KeyCode::End => self.move_cursor(isize::MAX / 2),
+            KeyCode::Char('u') if ctrl => {
+                self.query.clear();
+                self.update_matches();
+            }
+            KeyCode::Char('w') if ctrl => {
+                let trimmed = self.query.trim_end().len();
+                let word_start = self.query[..trimmed]
+                    .rfind(char::is_whitespace)
+                    .map(|i| i + 1)
+                    .unwrap_or(0);
+                self.query.truncate(word_start);
+                self.update_matches();
+            }
+            KeyCode::Backspace => {
+                self.query.pop();
+                self.update_matches();
+            }
+            KeyCode::Char(c) if !ctrl => {
+                self.query.push(c);
+                self.update_matches();
+            }
+            _ => {}
+        }
+        None
+    }
+
+    /// Draw the picker on the terminal
+    fn draw(&mut self, out: &mut impl Write, options: &PickOptions) -> io::Result<()> {
+        // Some terminals report no size, assume the classic 80x24
+        let (width, height) = match terminal::size()? {
+            (0, _) | (_, 0) => (80, 24),
+            (width, height) => (width as usize, height as usize),
+        };
+
+        let list_width = if options.preview.is_some() {
+            width * 55 / 100
+        } else {
+            width
+        };
+        let header_lines = 2 + usize::from(options.header.is_some());
+        let list_height = height.saturating_sub(header_lines).max(1);
+
+        // Keep the cursor visible
+        if self.cursor < self.offset {
+            self.offset = self.cursor;
+        } else if self.cursor >= self.offset + list_height {
+            self.offset = self.cursor + 1 - list_height;
+        }
+
+        queue!(out, terminal::Clear(ClearType::All), cursor::MoveTo(0, 0))?;
+        queue!(
+            out,
+            SetAttribute(Attribute::Bold),
+            Print(truncate(
+                &format!("{}{}", options.prompt, self.query),
+                width
+            )),
+            SetAttribute(Attribute::Reset),
+        )?;
+        let counter = if self.marked.is_empty() {
+            format!("  {}/{}", self.matches.len(), self.items.len())
+        } else {
+            format!(
+                "  {}/{} ({} selected)",
+                self.matches.len(),
+                self.items.len(),
+                self.marked.len()
+            )
+        };
+        queue!(
+            out,
+            cursor::MoveTo(0, 1),
+            SetAttribute(Attribute::Dim),
+            Print(counter),
+            SetAttribute(Attribute::Reset),
+        )?;
+        if let Some(ref header) = options.header {
+            queue!(out, cursor::MoveTo(0, 2), Print(truncate(header, width)))?;
+        }
+
+        for (row, &index) in self
+            .matches
+            .iter()
+            .enumerate()
+            .skip(self.offset)
+            .take(list_height)
+            .map(|(position, index)| (position - self.offset, index))
+        {
+            let is_selected = row + self.offset == self.cursor;
+            let is_marked = self.marked.contains(&index);
+            let line = format!(
+                "{}{} {}",
+                if is_selected { ">" } else { " " },
+                if is_marked { "*" } else { " " },
+                self.items[index]
+                    .fields
+                    .iter()
+                    .filter(|field| !field.is_empty())
+                    .cloned()
+                    .collect::<Vec<_>>()
+                    .join("  ")
+            );
+            queue!(out, cursor::MoveTo(0, (header_lines + row) as u16))?;
+            if is_selected {
+                queue!(out, SetAttribute(Attribute::Reverse))?;
+            }
+            queue!(
+                out,
+                Print(truncate(&line, list_width.saturating_sub(1))),
+                SetAttribute(Attribute::Reset)
+            )?;
+        }
+
+        if let (Some(preview), Some(index)) = (options.preview, self.selected()) {
+            let column = list_width as u16;
+            let preview_width = width.saturating_sub(list_width + 2);
+            for (row, line) in preview(index)
+                .lines()
+                .take(height.saturating_sub(1))
+                .enumerate()
+            {
+                queue!(
+                    out,
+                    cursor::MoveTo(column, (row + 1) as u16),
+                    Print("│ "),
+                    Print(truncate(line, preview_width))
+                )?;
+            }
+        }
+
+        out.flush()
+    }
+}
+
+/// Picker drawn directly in the terminal, used when fzf is not available
+pub struct BuiltinPicker;
+
+impl Picker for BuiltinPicker {
+    fn pick(&self, items: &[PickerItem], options: &PickOptions) -> Result<Option<Vec<usize>>> {
+        let _guard = TerminalGuard::enter()
+            .map_err(|e| YkError::Picker(format!("Failed to start the builtin picker: {}", e)))?;
+        let mut out = io::stderr();
+        let mut state = BuiltinState::new(items);
+
+        loop {
+            state.draw(&mut out, options).map_err(picker_error)?;
+
+            match event::read().map_err(picker_error)? {
+                Event::Key(key) if key.kind != KeyEventKind::Release => {
+                    let page = terminal::size()
+                        .map_err(picker_error)?
+                        .1
+                        .saturating_sub(3)
+                        .max(1) as usize;
+                    if let Some(result) = state.handle_key(key, page, options.multi) {
+                        return Ok(result);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(fields: &[&str]) -> PickerItem {
+        PickerItem {
+            fields: fields.iter().map(|field| field.to_string()).collect(),
+            hidden: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn fuzzy_score_matches_characters_in_order() {
+        assert!(fuzzy_score("dpl", "deploy").is_some());
+        assert_eq!(fuzzy_score("ped", "deploy"), None);
+        assert_eq!(fuzzy_score("x", "deploy"), None);
+    }
+
+    #[test]
+    fn fuzzy_score_is_case_sensitive_only_with_uppercase() {
+        assert!(fuzzy_score("dep", "DEPLOY").is_some());
+        assert!(fuzzy_score("Dep", "Deploy").is_some());
+        assert_eq!(fuzzy_score("Dep", "deploy"), None);
+    }
+
+    #[test]
+    fn fuzzy_score_prefers_consecutive_matches_and_word_starts() {
+        assert!(fuzzy_score("dep", "deploy") > fuzzy_score("dep", "dxexp"));
+        assert!(fuzzy_score("b", "a-b") > fuzzy_score("b", "ab"));
+        assert!(fuzzy_score("dp", "dxp") > fuzzy_score("dp", "dxxxxxxxxxp"));
+    }
+
+    #[test]
+    fn score_item_requires_every_token_and_takes_the_best_field() {
+        let deploy = item(&["deploy", "Deploy the site", "ops"]);
+        assert_eq!(score_item("", &deploy), Some(0));
+        let best = fuzzy_score("deploy", "deploy").unwrap() + fuzzy_score("ops", "ops").unwrap();
+        assert_eq!(score_item("deploy ops", &deploy), Some(best));
+        assert_eq!(score_item("deploy k8s", &deploy), None);
+    }
+}
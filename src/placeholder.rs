@@ -0,0 +1,485 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, IsTerminal, Write};
+
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::error::YkError;
+use crate::picker::{PickOptions, Picker, PickerItem};
+use crate::{apply_environment, FullCommandSnippet};
+
+/// Placeholder declaration on a command snippet
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct PlaceholderSpec {
+    /// Shown in the prompt
+    pub description: Option<String>,
+    /// Used when the input is empty
+    pub default: Option<String>,
+    /// Allowed values
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub choices: Vec<String>,
+    /// Regular expression the whole value must match
+    pub pattern: Option<String>,
+    /// Shell command whose output lines are offered as choices
+    pub command: Option<String>,
+    /// 1-based column of the selected line to use as value
+    pub column: Option<usize>,
+    /// Column delimiter, whitespace when not set
+    pub delimiter: Option<String>,
+}
+
+/// Placeholder occurrence found in snippet text, e.g. `{{port:8080}}`
+#[derive(Debug, Clone)]
+pub struct Placeholder {
+    pub name: String,
+    pub default: Option<String>,
+}
+
+/// Check whether a placeholder name is valid
+///
+/// Names must start with a letter or underscore so that templates of other
+/// tools (e.g. `{{.Names}}` for docker) are left untouched.
+fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Parse the inside of `{{ ... }}` into a placeholder
+fn parse_placeholder(inner: &str) -> Option<Placeholder> {
+    let (name, default) = match inner.split_once(':') {
+        Some((name, default)) => (name.trim(), Some(default.to_string())),
+        None => (inner.trim(), None),
+    };
+
+    if !is_valid_name(name) {
+        return None;
+    }
+
+    Some(Placeholder {
+        name: name.to_string(),
+        default,
+    })
+}
+
+/// Piece of text split around placeholders
+enum Segment<'a> {
+    Text(&'a str),
+    Placeholder(Placeholder),
+}
+
+/// Split text into literal parts and placeholders
+fn split_segments(text: &str) -> Vec<Segment<'_>> {
+    let mut segments = Vec::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find("{{") {
+        let after_open = &rest[start + 2..];
+        let Some(end) = after_open.find("}}") else {
+            break;
+        };
+
+        match parse_placeholder(&after_open[..end]) {
+            Some(placeholder) => {
+                segments.push(Segment::Text(&rest[..start]));
+                segments.push(Segment::Placeholder(placeholder));
+                rest = &after_open[end + 2..];
+            }
+            None => {
+                segments.push(Segment::Text(&rest[..start + 2]));
+                rest = after_open;
+            }
+        }
+    }
+
+    segments.push(Segment::Text(rest));
+    segments
+}
+
+/// Find all placeholders in text, in order of appearance
+pub fn find_placeholders(text: &str) -> Vec<Placeholder> {
+    split_segments(text)
+        .into_iter()
+        .filter_map(|segment| match segment {
+            Segment::Placeholder(placeholder) => Some(placeholder),
+            Segment::Text(_) => None,
+        })
+        .collect()
+}
+
+/// Whether text contains placeholders
+pub fn is_template(text: &str) -> bool {
+    !find_placeholders(text).is_empty()
+}
+
+/// Replace placeholders in text with the given values
+///
+/// Placeholders without a value are kept as they are.
+pub fn substitute(text: &str, values: &HashMap<String, String>) -> String {
+    let mut result = String::with_capacity(text.len());
+
+    for segment in split_segments(text) {
+        match segment {
+            Segment::Text(part) => result.push_str(part),
+            Segment::Placeholder(placeholder) => match values.get(&placeholder.name) {
+                Some(value) => result.push_str(value),
+                None => {
+                    result.push_str("{{");
+                    result.push_str(&placeholder.name);
+                    if let Some(default) = placeholder.default {
+                        result.push(':');
+                        result.push_str(&default);
+                    }
+                    result.push_str("}}");
+                }
+            },
+        }
+    }
+
+    result
+}
+
+/// Collect the unique placeholders of several texts, in order of appearance
+///
+/// The first inline default of a placeholder wins.
+pub fn collect_placeholders<'a>(texts: impl IntoIterator<Item = &'a str>) -> Vec<Placeholder> {
+    let mut placeholders: Vec<Placeholder> = Vec::new();
+
+    for text in texts {
+        for placeholder in find_placeholders(text) {
+            match placeholders.iter_mut().find(|p| p.name == placeholder.name) {
+                Some(existing) => {
+                    if existing.default.is_none() {
+                        existing.default = placeholder.default;
+                    }
+                }
+                None => placeholders.push(placeholder),
+            }
+        }
+    }
+
+    placeholders
+}
+
+/// Check a value against the choices and pattern of a placeholder declaration
+fn validate_value(
+    value: &str,
+    spec: &PlaceholderSpec,
+    pattern: Option<&Regex>,
+) -> Result<(), String> {
+    if !spec.choices.is_empty() && !spec.choices.iter().any(|choice| choice == value) {
+        return Err(format!(
+            "'{}' is not one of: {}",
+            value,
+            spec.choices.join(", ")
+        ));
+    }
+
+    if let Some(pattern) = pattern {
+        if !pattern.is_match(value) {
+            return Err(format!(
+                "'{}' does not match pattern {}",
+                value,
+                spec.pattern.as_deref().unwrap_or_default()
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Run a choices command through the shell and return its output lines
+///
+/// It runs in the working directory and environment of the snippet, filled
+/// in with the values known so far, so that e.g. `git branch` lists the
+/// branches of the right repository.
+fn run_choices_command(
+    command: &str,
+    snippet: &FullCommandSnippet,
+) -> Result<Vec<String>, YkError> {
+    // Paths still waiting for a value are left out
+    let mut snippet = snippet.clone();
+    for path in [&mut snippet.cwd, &mut snippet.env_file] {
+        if path
+            .as_ref()
+            .is_some_and(|path| is_template(&path.to_string_lossy()))
+        {
+            *path = None;
+        }
+    }
+
+    let mut process = snippet.shell.command(command);
+    apply_environment(&mut process, &snippet)?;
+    let output = process.output().map_err(|e| {
+        YkError::Execution(format!(
+            "Failed to run choices command '{}': {}",
+            command, e
+        ))
+    })?;
+
+    if !output.status.success() {
+        return Err(YkError::Execution(format!(
+            "Choices command '{}' failed: {}",
+            command,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.to_string())
+        .collect())
+}
+
+/// Whether stdin is redirected and has no more input
+///
+/// A terminal is never reported, checking it would wait for the user before
+/// the prompt is shown.
+fn stdin_at_eof() -> io::Result<bool> {
+    let stdin = io::stdin();
+    if stdin.is_terminal() {
+        return Ok(false);
+    }
+    let at_eof = stdin.lock().fill_buf()?.is_empty();
+    Ok(at_eof)
+}
+
+/// Extract the configured column from a selected line
+fn select_column(line: &str, spec: &PlaceholderSpec) -> Option<String> {
+    let Some(column) = spec.column else {
+        return Some(line.trim().to_string());
+    };
+    let index = column.checked_sub(1)?;
+
+    let field = match spec.delimiter {
+        Some(ref delimiter) => line.split(delimiter.as_str()).nth(index),
+        None => line.split_whitespace().nth(index),
+    };
+    field.map(|field| field.trim().to_string())
+}
+
+/// Interactively ask for placeholder values
+///
+/// Values already present in `preset` are validated but not asked for.
+/// Placeholders declaring a `command` are picked from its output.
+pub fn prompt_placeholders(
+    command: &FullCommandSnippet,
+    preset: &HashMap<String, String>,
+    picker: &dyn Picker,
+) -> Result<HashMap<String, String>, YkError> {
+    let mut values = HashMap::new();
+
+    for placeholder in command.placeholders() {
+        let spec = command
+            .placeholders
+            .get(&placeholder.name)
+            .cloned()
+            .unwrap_or_default();
+        let pattern = match spec.pattern {
+            Some(ref pattern) => Some(Regex::new(&format!("^(?:{})$", pattern)).map_err(|e| {
+                YkError::Config(format!(
+                    "Invalid pattern for placeholder '{}': {}",
+                    placeholder.name, e
+                ))
+            })?),
+            None => None,
+        };
+        let default = placeholder.default.clone().or_else(|| spec.default.clone());
+
+        if let Some(value) = preset.get(&placeholder.name) {
+            validate_value(value, &spec, pattern.as_ref()).map_err(|e| {
+                YkError::Execution(format!("Invalid value for '{}': {}", placeholder.name, e))
+            })?;
+            values.insert(placeholder.name.clone(), value.clone());
+            continue;
+        }
+
+        if let Some(ref choices_command) = spec.command {
+            // Earlier values may be used inside the choices command
+            let lines = run_choices_command(
+                &substitute(choices_command, &values),
+                &command.fill_placeholders(&values),
+            )?;
+            if lines.is_empty() {
+                return Err(YkError::Execution(format!(
+                    "Choices command for placeholder '{}' produced no output",
+                    placeholder.name
+                )));
+            }
+
+            let items: Vec<PickerItem> = lines
+                .iter()
+                .map(|line| PickerItem {
+                    fields: vec![line.clone()],
+                    ..Default::default()
+                })
+                .collect();
+            let options = PickOptions {
+                prompt: format!("Select {} 🔍: ", placeholder.name),
+                header: spec.description.clone(),
+                ..Default::default()
+            };
+            let index = picker.pick_one(&items, &options)?.ok_or_else(|| {
+                YkError::Execution(format!(
+                    "No value selected for placeholder '{}'",
+                    placeholder.name
+                ))
+            })?;
+            let selected = &lines[index];
+            let value = select_column(selected, &spec).ok_or_else(|| {
+                YkError::Execution(format!(
+                    "Selected line has no column {} for placeholder '{}'",
+                    spec.column.unwrap_or_default(),
+                    placeholder.name
+                ))
+            })?;
+
+            if let Some(ref pattern) = pattern {
+                if !pattern.is_match(&value) {
+                    return Err(YkError::Execution(format!(
+                        "Invalid value for '{}': '{}' does not match pattern {}",
+                        placeholder.name,
+                        value,
+                        spec.pattern.as_deref().unwrap_or_default()
+                    )));
+                }
+            }
+            values.insert(placeholder.name.clone(), value);
+            continue;
+        }
+
+        if !spec.choices.is_empty() {
+            println!(
+                "Choices for {}: {}",
+                placeholder.name,
+                spec.choices.join(", ")
+            );
+        }
+
+        loop {
+            // Without input, e.g. `yk run` in CI, the default is used without asking
+            if stdin_at_eof()? {
+                let value = default.clone().ok_or_else(|| {
+                    YkError::Execution(format!(
+                        "No value given for placeholder '{}'",
+                        placeholder.name
+                    ))
+                })?;
+                validate_value(&value, &spec, pattern.as_ref()).map_err(|e| {
+                    YkError::Execution(format!("Invalid value for '{}': {}", placeholder.name, e))
+                })?;
+                values.insert(placeholder.name.clone(), value);
+                break;
+            }
+
+            print!("Enter value for {}", placeholder.name);
+            if let Some(ref description) = spec.description {
+                print!(" ({})", description);
+            }
+            if let Some(ref default) = default {
+                print!(" [default: {}]", default);
+            }
+            print!(": ");
+            io::stdout().flush()?;
+
+            let mut input = String::new();
+            let at_eof = io::stdin().read_line(&mut input)? == 0;
+            if at_eof {
+                // Ctrl-D in a terminal, the prompt line is still open
+                println!();
+                if default.is_none() {
+                    return Err(YkError::Execution(format!(
+                        "No value given for placeholder '{}'",
+                        placeholder.name
+                    )));
+                }
+            }
+            let input = input.trim_end_matches(['\r', '\n']);
+
+            let value = match (input.is_empty(), &default) {
+                (true, Some(default)) => default.clone(),
+                (true, None) => {
+                    println!("Value for {} cannot be empty", placeholder.name);
+                    continue;
+                }
+                (false, _) => input.to_string(),
+            };
+
+            match validate_value(&value, &spec, pattern.as_ref()) {
+                Ok(()) => {
+                    values.insert(placeholder.name.clone(), value);
+                    break;
+                }
+                Err(e) => println!("Invalid value: {}", e),
+            }
+        }
+    }
+
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Segments as strings, placeholders written as `<name>` or `<name:default>`
+    fn segments(text: &str) -> Vec<String> {
+        split_segments(text)
+            .into_iter()
+            .map(|segment| match segment {
+                Segment::Text(part) => part.to_string(),
+                Segment::Placeholder(Placeholder {
+                    name,
+                    default: Some(default),
+                }) => format!("<{}:{}>", name, default),
+                Segment::Placeholder(Placeholder {
+                    name,
+                    default: None,
+                }) => format!("<{}>", name),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn split_segments_finds_placeholders_and_defaults() {
+        assert_eq!(
+            segments("ssh {{user:root}}@{{ host }} -p {{port:}}"),
+            ["ssh ", "<user:root>", "@", "<host>", " -p ", "<port:>", ""]
+        );
+        assert_eq!(
+            segments("curl {{url:http://localhost:8080}}"),
+            ["curl ", "<url:http://localhost:8080>", ""]
+        );
+    }
+
+    #[test]
+    fn split_segments_keeps_foreign_templates() {
+        assert_eq!(
+            segments("docker ps --format '{{.Names}}' {{1x}}"),
+            ["docker ps --format '{{", ".Names}}' {{", "1x}}"]
+        );
+        assert!(!is_template("docker ps --format '{{.Names}}'"));
+        assert!(!is_template("echo {{unterminated"));
+    }
+
+    #[test]
+    fn substitute_replaces_known_placeholders() {
+        let values = HashMap::from([
+            ("host".to_string(), "example.com".to_string()),
+            ("port".to_string(), "".to_string()),
+        ]);
+        assert_eq!(
+            substitute("ssh {{ host }} -p {{port:22}} {{user:root}}", &values),
+            "ssh example.com -p  {{user:root}}"
+        );
+        assert_eq!(
+            substitute("echo '{{.Names}}' {{missing}}", &values),
+            "echo '{{.Names}}' {{missing}}"
+        );
+    }
+}
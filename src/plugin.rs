@@ -0,0 +1,505 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+use clap::Subcommand;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Result, YkError};
+use crate::sources::{self, Scope};
+use crate::{diagnostics, format, generator, manifest};
+use crate::{get_config_file, get_plugin_lock_file, get_plugins_dir, load_config, ID_SEPARATOR};
+
+/// Actions of `yk plugin`
+#[derive(Debug, Subcommand)]
+pub enum PluginAction {
+    /// Install a plugin from a git URL or a local repository
+    Install {
+        /// Repository to clone
+        source: String,
+        /// Plugin name, the repository name by default
+        #[arg(short, long)]
+        name: Option<String>,
+        /// Branch, tag or commit to pin the plugin to
+        #[arg(short = 'r', long = "ref", value_name = "REF")]
+        reference: Option<String>,
+    },
+    /// Update installed plugins to the latest commit of their ref
+    Update {
+        /// Plugins to update, all installed plugins by default
+        names: Vec<String>,
+    },
+    /// Remove an installed plugin
+    Remove {
+        /// Plugin name
+        name: String,
+    },
+    /// Load a plugin again after `yk plugin disable`, or one its manifest disables
+    Enable {
+        /// Plugin name
+        name: String,
+    },
+    /// Stop loading a plugin without removing it
+    Disable {
+        /// Plugin name
+        name: String,
+    },
+    /// List plugins with their versions
+    List,
+}
+
+/// Plugin installed by `yk plugin install`, as recorded in the lockfile
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LockedPlugin {
+    /// Git URL or path the plugin was cloned from
+    pub source: String,
+    /// Branch, tag or commit the plugin is pinned to
+    #[serde(rename = "ref", default, skip_serializing_if = "Option::is_none")]
+    pub reference: Option<String>,
+    /// Checked out commit
+    pub commit: String,
+    /// Output of `git describe`, e.g. `v1.2.0-3-gabc1234`
+    pub version: String,
+}
+
+/// Installed plugins by name
+type Lockfile = BTreeMap<String, LockedPlugin>;
+
+fn load_lockfile(path: &Path) -> Result<Lockfile> {
+    match fs::read_to_string(path) {
+        Ok(content) => format::parse_document(path, &content),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Lockfile::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn save_lockfile(path: &Path, lockfile: &Lockfile) -> Result<()> {
+    fs::write(path, format::to_string(path, lockfile)?)?;
+    Ok(())
+}
+
+/// Run git and return its trimmed output
+fn git(name: &str, dir: Option<&Path>, args: &[&str]) -> Result<String> {
+    let mut command = Command::new("git");
+    if let Some(dir) = dir {
+        command.arg("-C").arg(dir);
+    }
+    let output = command.args(args).output().map_err(|e| YkError::Plugin {
+        name: name.to_string(),
+        message: format!("failed to run git: {}", e),
+    })?;
+
+    if !output.status.success() {
+        return Err(YkError::Plugin {
+            name: name.to_string(),
+            message: format!(
+                "git {} failed: {}",
+                args.first().unwrap_or(&""),
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        });
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Name of the repository, e.g. `ops` for `https://example.com/team/ops.git`
+fn name_from_source(source: &str) -> Option<String> {
+    let last = source
+        .trim_end_matches(['/', '\\'])
+        .rsplit(['/', '\\', ':'])
+        .next()?;
+    let name = last.strip_suffix(".git").unwrap_or(last);
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+/// Short form of a commit hash
+fn short(commit: &str) -> &str {
+    &commit[..commit.len().min(7)]
+}
+
+/// Check out the pinned ref, or the default branch of the remote
+///
+/// Branches are resolved on the remote so that updates follow them.
+fn checkout(name: &str, dir: &Path, reference: Option<&str>) -> Result<()> {
+    let target = match reference {
+        Some(reference) => {
+            let remote_branch = format!("origin/{}", reference);
+            if git(
+                name,
+                Some(dir),
+                &["rev-parse", "--verify", "--quiet", &remote_branch],
+            )
+            .is_ok()
+            {
+                remote_branch
+            } else {
+                reference.to_string()
+            }
+        }
+        None => "origin/HEAD".to_string(),
+    };
+    git(
+        name,
+        Some(dir),
+        &["checkout", "--quiet", "--detach", &target, "--"],
+    )?;
+    Ok(())
+}
+
+/// Commit and version of a checked out plugin
+fn describe(name: &str, dir: &Path) -> Result<(String, String)> {
+    let commit = git(name, Some(dir), &["rev-parse", "HEAD"])?;
+    let version = git(name, Some(dir), &["describe", "--tags", "--always"])?;
+    Ok((commit, version))
+}
+
+/// Whether a plugin directory has a snippet file or a generator
+fn has_snippets(plugin_dir: &Path, name: &str) -> bool {
+    format::find_file(plugin_dir, name).is_some() || generator::find_generator(plugin_dir).is_some()
+}
+
+fn install(source: &str, name: Option<String>, reference: Option<String>) -> Result<()> {
+    let name = name
+        .or_else(|| name_from_source(source))
+        .ok_or_else(|| YkError::Config(format!("Cannot derive a plugin name from {:?}", source)))?;
+    if name.contains(['/', '\\', ID_SEPARATOR]) || name == "." || name == ".." {
+        return Err(YkError::Config(format!("Invalid plugin name '{}'", name)));
+    }
+    let plugins_dir = get_plugins_dir();
+    let plugin_dir = plugins_dir.join(&name);
+    if plugin_dir.exists() {
+        return Err(YkError::Plugin {
+            name,
+            message: format!("{:?} already exists", plugin_dir),
+        });
+    }
+
+    // Local repositories are given relative to the current directory
+    let source = match Path::new(source) {
+        path if path.exists() => fs::canonicalize(path)?.to_string_lossy().to_string(),
+        _ => source.to_string(),
+    };
+
+    fs::create_dir_all(&plugins_dir)?;
+    let plugin_dir_arg = plugin_dir.to_string_lossy().to_string();
+    git(
+        &name,
+        None,
+        &["clone", "--quiet", "--", &source, &plugin_dir_arg],
+    )?;
+
+    // Do not leave a half installed plugin behind
+    let installed = checkout(&name, &plugin_dir, reference.as_deref())
+        .and_then(|_| describe(&name, &plugin_dir))
+        .and_then(|described| match has_snippets(&plugin_dir, &name) {
+            true => Ok(described),
+            false => Err(YkError::Plugin {
+                name: name.clone(),
+                message: format!(
+                    "repository has no {}.json, {}.toml, {}.yaml or generator, the snippet file must be named after the plugin",
+                    name, name, name
+                ),
+            }),
+        });
+    let (commit, version) = match installed {
+        Ok(described) => described,
+        Err(e) => {
+            let _ = fs::remove_dir_all(&plugin_dir);
+            return Err(e);
+        }
+    };
+
+    let lock_file = get_plugin_lock_file();
+    let mut lockfile = load_lockfile(&lock_file)?;
+    println!("Installed {} {} ({})", name, version, short(&commit));
+    lockfile.insert(
+        name,
+        LockedPlugin {
+            source,
+            reference,
+            commit,
+            version,
+        },
+    );
+    save_lockfile(&lock_file, &lockfile)
+}
+
+fn update(names: &[String]) -> Result<()> {
+    let lock_file = get_plugin_lock_file();
+    let mut lockfile = load_lockfile(&lock_file)?;
+
+    let names: Vec<String> = if names.is_empty() {
+        lockfile.keys().cloned().collect()
+    } else {
+        names.to_vec()
+    };
+    if names.is_empty() {
+        println!("No plugins installed");
+        return Ok(());
+    }
+
+    for name in names {
+        let locked = lockfile.get_mut(&name).ok_or_else(|| YkError::Plugin {
+            name: name.clone(),
+            message: "not installed with `yk plugin install`".to_string(),
+        })?;
+        let plugin_dir = get_plugins_dir().join(&name);
+
+        git(
+            &name,
+            Some(&plugin_dir),
+            &["fetch", "--quiet", "--tags", "origin"],
+        )?;
+        checkout(&name, &plugin_dir, locked.reference.as_deref())?;
+        let (commit, version) = describe(&name, &plugin_dir)?;
+
+        if commit == locked.commit {
+            println!("{} is up to date ({})", name, locked.version);
+        } else {
+            println!(
+                "Updated {} {} ({}) -> {} ({})",
+                name,
+                locked.version,
+                short(&locked.commit),
+                version,
+                short(&commit)
+            );
+        }
+        if !has_snippets(&plugin_dir, &name) {
+            diagnostics::warn(YkError::Plugin {
+                name: name.clone(),
+                message: "snippet file or generator was removed by the update".to_string(),
+            });
+        }
+        locked.commit = commit;
+        locked.version = version;
+        // Save after every plugin so that a failing one keeps earlier updates
+        save_lockfile(&lock_file, &lockfile)?;
+    }
+    Ok(())
+}
+
+fn remove(name: &str) -> Result<()> {
+    let lock_file = get_plugin_lock_file();
+    let mut lockfile = load_lockfile(&lock_file)?;
+    if lockfile.remove(name).is_none() {
+        return Err(YkError::Plugin {
+            name: name.to_string(),
+            message: "not installed with `yk plugin install`, remove its directory by hand"
+                .to_string(),
+        });
+    }
+
+    let plugin_dir = get_plugins_dir().join(name);
+    if plugin_dir.exists() {
+        fs::remove_dir_all(&plugin_dir)?;
+    }
+    save_lockfile(&lock_file, &lockfile)?;
+    println!("Removed {}", name);
+    Ok(())
+}
+
+/// Enable or disable a plugin in the configuration file
+///
+/// Both lists are kept consistent, so that the plugin ends up in at most one
+/// of them. A plugin its manifest disables is listed in `enabled_plugins`.
+fn set_enabled(name: &str, enabled: bool) -> Result<()> {
+    let config = load_config()?;
+    let plugin_file = sources::plugin_files(&config)
+        .into_iter()
+        .find(|plugin_file| plugin_file.name == name)
+        .ok_or_else(|| YkError::Plugin {
+            name: name.to_string(),
+            message: "no plugin with this name".to_string(),
+        })?;
+
+    let mut disabled_plugins = config.disabled_plugins.clone();
+    let mut enabled_plugins = config.enabled_plugins.clone();
+    disabled_plugins.retain(|listed| listed != name);
+    enabled_plugins.retain(|listed| listed != name);
+    if enabled {
+        let manifest = if plugin_file.config_file.is_file() {
+            manifest::load(&plugin_file)?
+        } else {
+            None
+        };
+        if manifest.is_some_and(|manifest| manifest.disabled) {
+            enabled_plugins.push(name.to_string());
+        }
+    } else {
+        disabled_plugins.push(name.to_string());
+    }
+
+    let config_file = get_config_file();
+    let content = fs::read_to_string(&config_file)?;
+    let content = format::set_list(
+        &config_file,
+        &content,
+        "disabled_plugins",
+        &disabled_plugins,
+    )?;
+    let content = format::set_list(&config_file, &content, "enabled_plugins", &enabled_plugins)?;
+    fs::write(&config_file, content)?;
+
+    if enabled {
+        println!("Enabled {}", name);
+    } else {
+        println!("Disabled {}", name);
+    }
+    Ok(())
+}
+
+fn list() -> Result<()> {
+    let config = load_config()?;
+    let lockfile = load_lockfile(&get_plugin_lock_file())?;
+    let plugin_files = sources::plugin_files_in(&get_plugins_dir(), Scope::User);
+    if plugin_files.is_empty() {
+        println!("No plugins installed");
+        return Ok(());
+    }
+
+    for plugin_file in plugin_files {
+        let manifest = if plugin_file.config_file.is_file() {
+            manifest::load(&plugin_file)
+        } else {
+            Ok(None)
+        };
+
+        match manifest {
+            Ok(Some(ref manifest)) => {
+                let title = match manifest.name {
+                    Some(ref name) if *name != plugin_file.name => {
+                        format!("{} ({})", plugin_file.name, name)
+                    }
+                    _ => plugin_file.name.clone(),
+                };
+                println!("{} {}", title, manifest.summary());
+            }
+            _ => println!("{}", plugin_file.name),
+        }
+
+        match lockfile.get(&plugin_file.name) {
+            Some(locked) => {
+                let mut line = format!(
+                    "  installed {} ({}) from {}",
+                    locked.version,
+                    short(&locked.commit),
+                    locked.source
+                );
+                if let Some(ref reference) = locked.reference {
+                    line.push_str(&format!(", pinned to {}", reference));
+                }
+                println!("{}", line);
+            }
+            None => println!("  local"),
+        }
+
+        // Requirements only matter for plugins that are loaded
+        let enable_hint = format!("enable with `yk plugin enable {}`", plugin_file.name);
+        match manifest {
+            _ if !config.is_plugin_enabled(&plugin_file.name, None) => {
+                println!("  disabled, {}", enable_hint);
+            }
+            Ok(Some(manifest)) => {
+                if !config.is_plugin_enabled(&plugin_file.name, Some(&manifest)) {
+                    println!("  disabled by its manifest, {}", enable_hint);
+                } else if let Some(reason) = manifest.unmet_requirement() {
+                    println!("  disabled: {}", reason);
+                }
+            }
+            Ok(None) => {}
+            Err(e) => println!("  invalid manifest: {}", e),
+        }
+    }
+    Ok(())
+}
+
+/// Run a `yk plugin` action
+pub fn plugin_command(action: PluginAction) -> Result<()> {
+    match action {
+        PluginAction::Install {
+            source,
+            name,
+            reference,
+        } => install(&source, name, reference),
+        PluginAction::Update { names } => update(&names),
+        PluginAction::Remove { name } => remove(&name),
+        PluginAction::Enable { name } => set_enabled(&name, true),
+        PluginAction::Disable { name } => set_enabled(&name, false),
+        PluginAction::List => list(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{get_config_dir, set_config_dir};
+
+    /// Run git with a fixed identity, so commits work without a global configuration
+    fn run_git(dir: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .args(["-c", "user.name=yk", "-c", "user.email=yk@example.com"])
+            .arg("-C")
+            .arg(dir)
+            .args(args)
+            .status()
+            .unwrap();
+        assert!(status.success(), "git {:?} failed", args);
+    }
+
+    /// Commit a snippet file to the working repository, tag it and push it to the bare one
+    fn release(work: &Path, tag: &str, args: &str) {
+        fs::write(
+            work.join("ops.json"),
+            format!(r#"{{"deploy": {{"args": {}}}}}"#, args),
+        )
+        .unwrap();
+        run_git(work, &["add", "ops.json"]);
+        run_git(work, &["commit", "--quiet", "-m", tag]);
+        run_git(work, &["tag", tag]);
+        run_git(work, &["push", "--quiet", "--tags", "origin", "HEAD"]);
+    }
+
+    #[test]
+    fn install_and_update_from_a_local_repository() {
+        let temp = tempfile::tempdir().unwrap();
+        let bare = temp.path().join("ops.git");
+        let work = temp.path().join("work");
+        let config_dir = temp.path().join("config");
+        fs::create_dir_all(&bare).unwrap();
+        fs::create_dir_all(&work).unwrap();
+        fs::create_dir_all(&config_dir).unwrap();
+        run_git(
+            &bare,
+            &["init", "--quiet", "--bare", "--initial-branch=main"],
+        );
+        run_git(&work, &["init", "--quiet", "--initial-branch=main"]);
+        run_git(&work, &["remote", "add", "origin", bare.to_str().unwrap()]);
+        release(&work, "v1.0.0", r#"["./deploy.sh"]"#);
+        set_config_dir(config_dir.clone());
+        // Never touch the plugins of the developer running the tests
+        assert_eq!(get_config_dir(), config_dir);
+
+        install(bare.to_str().unwrap(), None, None).unwrap();
+        let installed = config_dir.join("plugins").join("ops").join("ops.json");
+        assert!(fs::read_to_string(&installed)
+            .unwrap()
+            .contains("./deploy.sh"));
+        let lockfile = load_lockfile(&get_plugin_lock_file()).unwrap();
+        assert_eq!(lockfile["ops"].version, "v1.0.0");
+        let first_commit = lockfile["ops"].commit.clone();
+
+        release(&work, "v1.1.0", r#"["./deploy.sh", "--all"]"#);
+        update(&[]).unwrap();
+        assert!(fs::read_to_string(&installed).unwrap().contains("--all"));
+        let lockfile = load_lockfile(&get_plugin_lock_file()).unwrap();
+        assert_eq!(lockfile["ops"].version, "v1.1.0");
+        assert_ne!(lockfile["ops"].commit, first_commit);
+
+        // Installing over an existing plugin fails and leaves it in place
+        assert!(install(bare.to_str().unwrap(), None, None).is_err());
+        assert!(installed.exists());
+    }
+}
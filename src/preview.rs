@@ -0,0 +1,388 @@
+use std::fs;
+use std::path::Path;
+
+use crate::FullCommandSnippet;
+
+/// Number of entry point or script lines shown in the preview
+const SOURCE_PREVIEW_LINES: usize = 30;
+
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const MAGENTA: &str = "\x1b[35m";
+const CYAN: &str = "\x1b[36m";
+const RESET: &str = "\x1b[0m";
+
+/// Languages known to the entry point highlighter
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Language {
+    Shell,
+    Python,
+    JavaScript,
+    Nu,
+    PowerShell,
+    Ruby,
+    Plain,
+}
+
+impl Language {
+    /// Language of a file extension or interpreter name
+    fn by_name(name: &str) -> Option<Language> {
+        match name {
+            "sh" | "bash" | "zsh" | "fish" | "ksh" | "dash" => Some(Language::Shell),
+            "py" | "python" | "python3" => Some(Language::Python),
+            "js" | "mjs" | "cjs" | "ts" | "node" | "deno" => Some(Language::JavaScript),
+            "nu" => Some(Language::Nu),
+            "ps1" | "pwsh" | "powershell" => Some(Language::PowerShell),
+            "rb" | "ruby" => Some(Language::Ruby),
+            _ => None,
+        }
+    }
+
+    /// Language named by a shebang line, e.g. `#!/usr/bin/env python3`
+    fn from_shebang(first_line: &str) -> Option<Language> {
+        first_line
+            .strip_prefix("#!")
+            .and_then(|shebang| shebang.split_whitespace().last())
+            .and_then(|interpreter| interpreter.rsplit('/').next())
+            .and_then(Language::by_name)
+    }
+
+    /// Detect the language from the file extension or the shebang line
+    fn detect(path: &Path, first_line: &str) -> Language {
+        let extension = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+
+        Language::by_name(&extension)
+            .or_else(|| Language::from_shebang(first_line))
+            .unwrap_or(Language::Plain)
+    }
+
+    /// Detect the language of an inline script from its interpreter
+    fn for_interpreter(interpreter: &Path, first_line: &str) -> Language {
+        let name = interpreter
+            .file_stem()
+            .map(|name| name.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+
+        Language::by_name(&name)
+            .or_else(|| Language::from_shebang(first_line))
+            .unwrap_or(Language::Plain)
+    }
+
+    fn line_comment(self) -> Option<&'static str> {
+        match self {
+            Language::JavaScript => Some("//"),
+            Language::Plain => None,
+            _ => Some("#"),
+        }
+    }
+
+    fn keywords(self) -> &'static [&'static str] {
+        match self {
+            Language::Shell => &[
+                "if", "then", "else", "elif", "fi", "for", "while", "until", "do", "done", "case",
+                "esac", "function", "return", "in", "local", "export", "set", "end",
+            ],
+            Language::Python => &[
+                "def", "class", "if", "elif", "else", "for", "while", "return", "import", "from",
+                "as", "with", "try", "except", "finally", "raise", "in", "not", "and", "or",
+                "None", "True", "False", "lambda", "pass", "yield",
+            ],
+            Language::JavaScript => &[
+                "function",
+                "const",
+                "let",
+                "var",
+                "if",
+                "else",
+                "for",
+                "while",
+                "return",
+                "import",
+                "from",
+                "export",
+                "async",
+                "await",
+                "class",
+                "new",
+                "try",
+                "catch",
+                "throw",
+                "null",
+                "undefined",
+                "true",
+                "false",
+            ],
+            Language::Nu => &[
+                "def", "let", "mut", "if", "else", "for", "in", "while", "loop", "return", "use",
+                "export", "module", "match", "true", "false", "null",
+            ],
+            Language::PowerShell => &[
+                "function", "param", "if", "else", "elseif", "foreach", "for", "while", "return",
+                "try", "catch", "finally", "throw", "switch",
+            ],
+            Language::Ruby => &[
+                "def", "class", "module", "if", "elsif", "else", "unless", "end", "do", "while",
+                "return", "require", "nil", "true", "false", "begin", "rescue",
+            ],
+            Language::Plain => &[],
+        }
+    }
+}
+
+/// Highlight a single line of source code with ANSI colors
+fn highlight_line(line: &str, language: Language) -> String {
+    if language == Language::Plain {
+        return line.to_string();
+    }
+
+    let chars: Vec<char> = line.chars().collect();
+    let comment: Vec<char> = language
+        .line_comment()
+        .unwrap_or_default()
+        .chars()
+        .collect();
+    let keywords = language.keywords();
+    let mut result = String::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+
+        if !comment.is_empty() && chars[i..].starts_with(&comment) {
+            let rest: String = chars[i..].iter().collect();
+            result.push_str(&format!("{}{}{}", DIM, rest, RESET));
+            break;
+        }
+
+        if c == '"' || c == '\'' || c == '`' {
+            let mut end = i + 1;
+            while end < chars.len() && chars[end] != c {
+                if chars[end] == '\\' && c != '\'' {
+                    end += 1;
+                }
+                end += 1;
+            }
+            let end = (end + 1).min(chars.len());
+            let literal: String = chars[i..end].iter().collect();
+            result.push_str(&format!("{}{}{}", GREEN, literal, RESET));
+            i = end;
+            continue;
+        }
+
+        if c.is_alphanumeric() || c == '_' {
+            let mut end = i;
+            while end < chars.len() && (chars[end].is_alphanumeric() || chars[end] == '_') {
+                end += 1;
+            }
+            let word: String = chars[i..end].iter().collect();
+            if keywords.contains(&word.as_str()) {
+                result.push_str(&format!("{}{}{}", MAGENTA, word, RESET));
+            } else if word.chars().all(|c| c.is_ascii_digit()) {
+                result.push_str(&format!("{}{}{}", YELLOW, word, RESET));
+            } else {
+                result.push_str(&word);
+            }
+            i = end;
+            continue;
+        }
+
+        if c == '$' && language != Language::Python {
+            let mut end = i + 1;
+            while end < chars.len() && (chars[end].is_alphanumeric() || "_{}".contains(chars[end]))
+            {
+                end += 1;
+            }
+            let variable: String = chars[i..end].iter().collect();
+            result.push_str(&format!("{}{}{}", CYAN, variable, RESET));
+            i = end;
+            continue;
+        }
+
+        result.push(c);
+        i += 1;
+    }
+
+    result
+}
+
+/// Renders preview lines, optionally with ANSI colors
+struct PreviewWriter {
+    color: bool,
+    lines: Vec<String>,
+}
+
+impl PreviewWriter {
+    fn paint(&self, style: &str, text: &str) -> String {
+        if self.color {
+            format!("{}{}{}", style, text, RESET)
+        } else {
+            text.to_string()
+        }
+    }
+
+    fn line(&mut self, text: impl Into<String>) {
+        self.lines.push(text.into());
+    }
+
+    fn field(&mut self, name: &str, value: impl AsRef<str>) {
+        let name = self.paint(BOLD, &format!("{}:", name));
+        self.lines.push(format!("{} {}", name, value.as_ref()));
+    }
+
+    fn section(&mut self, name: &str) {
+        self.lines.push(String::new());
+        let name = self.paint(BOLD, name);
+        self.lines.push(name);
+    }
+
+    /// Source code, highlighted and cut after a number of lines
+    fn source(&mut self, content: &str, language: Language) {
+        for line in content.lines().take(SOURCE_PREVIEW_LINES) {
+            let line = if self.color {
+                highlight_line(line, language)
+            } else {
+                line.to_string()
+            };
+            self.line(line);
+        }
+        if content.lines().count() > SOURCE_PREVIEW_LINES {
+            let more = self.paint(DIM, "...");
+            self.line(more);
+        }
+    }
+}
+
+/// Render the preview of a command snippet
+pub fn render_preview(command: &FullCommandSnippet, color: bool) -> String {
+    let mut out = PreviewWriter {
+        color,
+        lines: Vec::new(),
+    };
+
+    let title = out.paint(BOLD, &command.id);
+    out.line(title);
+    if let Some(ref description) = command.description {
+        out.line(description.clone());
+    }
+    out.line(String::new());
+
+    if !command.labels.is_empty() {
+        out.field("Labels", command.labels.join(", "));
+    }
+    out.field("Source", command.config_file.to_string_lossy());
+    if let Some(ref reason) = command.disabled {
+        out.field("Disabled", reason);
+    }
+    match command.shadows {
+        Some(ref id) => out.field(
+            "Scope",
+            format!("{}, overrides system command {}", command.scope.name(), id),
+        ),
+        None => out.field("Scope", command.scope.name()),
+    }
+
+    if let Some(ref script) = command.script {
+        let interpreter = command.script_interpreter();
+        out.section(&format!("Script ({})", interpreter.to_string_lossy()));
+        let first_line = script.lines().next().unwrap_or_default();
+        out.source(script, Language::for_interpreter(interpreter, first_line));
+    } else {
+        out.section("Command");
+        out.line(command.complete_command.clone());
+    }
+
+    if command.is_workflow() {
+        out.section("Steps");
+        for (position, step) in command.steps.iter().enumerate() {
+            let mut line = format!("  {}. {}", position + 1, step.command.name);
+            if step.confirm {
+                line.push_str(" [confirm]");
+            }
+            line.push_str(&format!(" (on failure: {})", step.on_failure.name()));
+            out.line(line);
+            out.line(format!("     {}", step.command.complete_command));
+        }
+    } else if command.is_script() {
+        if !command.args.is_empty() {
+            out.section("Arguments");
+            for (index, arg) in command.args.iter().enumerate() {
+                out.line(format!("  [{}] {}", index + 1, arg));
+            }
+        }
+    } else {
+        out.section("Arguments");
+        if command.if_shell {
+            // The argv the shell is started with, the command line included
+            let process = command.shell.command(&command.complete_command);
+            let argv = std::iter::once(process.get_program()).chain(process.get_args());
+            for (index, arg) in argv.enumerate() {
+                out.line(format!("  [{}] {}", index, arg.to_string_lossy()));
+            }
+            out.line(format!("  (shell: {})", command.shell.kind.name()));
+        } else {
+            for (index, arg) in command.argv().iter().enumerate() {
+                out.line(format!("  [{}] {}", index, arg));
+            }
+        }
+    }
+
+    if command.cwd.is_some()
+        || !command.env.is_empty()
+        || command.env_clear
+        || command.env_file.is_some()
+    {
+        out.section("Environment");
+        if let Some(ref cwd) = command.cwd {
+            out.field("  cwd", cwd.to_string_lossy());
+        }
+        if command.env_clear {
+            out.line("  (environment cleared)");
+        }
+        if let Some(ref env_file) = command.env_file {
+            out.field("  env file", env_file.to_string_lossy());
+        }
+        let mut env: Vec<(&String, &String)> = command.env.iter().collect();
+        env.sort();
+        for (key, value) in env {
+            out.line(format!("  {}={}", key, value));
+        }
+    }
+
+    let placeholders = command.placeholders();
+    if !placeholders.is_empty() {
+        out.section("Placeholders");
+        for placeholder in placeholders {
+            let spec = command.placeholders.get(&placeholder.name);
+            let mut line = format!("  {}", placeholder.name);
+            if let Some(description) = spec.and_then(|spec| spec.description.as_ref()) {
+                line.push_str(&format!(" - {}", description));
+            }
+            if let Some(default) = placeholder
+                .default
+                .as_ref()
+                .or_else(|| spec.and_then(|spec| spec.default.as_ref()))
+            {
+                line.push_str(&format!(" [default: {}]", default));
+            }
+            out.line(line);
+        }
+    }
+
+    if let Some(ref entry_point) = command.entry_point {
+        out.section(&format!("Entry point: {}", entry_point.to_string_lossy()));
+        match fs::read_to_string(entry_point) {
+            Ok(content) => {
+                let first_line = content.lines().next().unwrap_or_default();
+                out.source(&content, Language::detect(entry_point, first_line));
+            }
+            Err(e) => out.line(format!("  (cannot read: {})", e)),
+        }
+    }
+
+    out.lines.join("\n")
+}
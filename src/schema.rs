@@ -0,0 +1,82 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use clap::ValueEnum;
+use schemars::generate::SchemaSettings;
+use schemars::{JsonSchema, Schema};
+use serde_json::json;
+
+use crate::error::Result;
+use crate::manifest::{self, PluginManifest};
+use crate::{CommandSnippet, YkConfig};
+
+/// Schema file of the configuration file, written next to it
+pub const CONFIG_SCHEMA_FILE_NAME: &str = "config.schema.json";
+/// Schema file of snippet files, written next to the simple commands file
+pub const SNIPPETS_SCHEMA_FILE_NAME: &str = "snippets.schema.json";
+
+/// Files a schema can be generated for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SchemaKind {
+    /// config.json
+    Config,
+    /// simple_commands.json and plugin files
+    Snippets,
+}
+
+/// Generate the draft-07 schema of a type, the draft supported by most editors
+fn schema_for<T: JsonSchema>() -> Schema {
+    SchemaSettings::draft07()
+        .into_generator()
+        .into_root_schema_for::<T>()
+}
+
+/// Schema of a configuration or snippet file
+pub fn schema(kind: SchemaKind) -> Schema {
+    match kind {
+        SchemaKind::Config => {
+            let mut schema = schema_for::<YkConfig>();
+            schema.insert("title".to_string(), json!("yk configuration"));
+            schema
+        }
+        SchemaKind::Snippets => {
+            let mut schema = schema_for::<HashMap<String, CommandSnippet>>();
+            schema.insert("title".to_string(), json!("yk snippets"));
+            // Keys starting with `$` are not commands
+            let mut plugin = schema_for::<PluginManifest>();
+            plugin.remove("$schema");
+            schema.insert(
+                "properties".to_string(),
+                json!({
+                    "$schema": { "type": "string" },
+                    manifest::MANIFEST_KEY: plugin,
+                }),
+            );
+            schema
+        }
+    }
+}
+
+/// Schema serialized as pretty printed JSON
+pub fn schema_json(kind: SchemaKind) -> String {
+    serde_json::to_string_pretty(&schema(kind)).unwrap_or_default()
+}
+
+/// Write both schemas into a directory
+pub fn write_schemas(dir: &Path) -> Result<()> {
+    for (kind, file_name) in [
+        (SchemaKind::Config, CONFIG_SCHEMA_FILE_NAME),
+        (SchemaKind::Snippets, SNIPPETS_SCHEMA_FILE_NAME),
+    ] {
+        let path = dir.join(file_name);
+        fs::write(&path, schema_json(kind)).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("Failed to write schema {:?}: {}", path, e),
+            )
+        })?;
+    }
+    Ok(())
+}
@@ -0,0 +1,98 @@
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::{Result, YkError};
+use crate::shell::ShellKind;
+
+/// Inline script written to a temporary file while it runs
+///
+/// The file is removed when dropped. A file is used instead of piping the
+/// script to the interpreter so that the script can still read from stdin.
+pub struct ScriptFile {
+    path: PathBuf,
+}
+
+impl ScriptFile {
+    /// Write a script into a new file in the temporary directory
+    ///
+    /// The extension matches the interpreter, as some of them (cmd, PowerShell)
+    /// refuse to run files without it.
+    pub fn create(body: &str, interpreter: &Path) -> Result<ScriptFile> {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.subsec_nanos())
+            .unwrap_or_default();
+        let path = env::temp_dir().join(format!(
+            "yk-script-{}-{}.{}",
+            process::id(),
+            nanos,
+            extension(interpreter)
+        ));
+
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        // The script may contain filled in secrets, keep it private
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+
+        let mut file = options.open(&path).map_err(|e| {
+            YkError::Execution(format!("Failed to create script file {:?}: {}", path, e))
+        })?;
+        file.write_all(body.as_bytes()).map_err(|e| {
+            YkError::Execution(format!("Failed to write script file {:?}: {}", path, e))
+        })?;
+
+        Ok(ScriptFile { path })
+    }
+
+    /// Command running the script with the interpreter and the given arguments
+    pub fn command(&self, interpreter: &Path, args: &[String]) -> Command {
+        let mut command = Command::new(interpreter);
+        command.args(interpreter_args(interpreter));
+        command.arg(&self.path);
+        command.args(args);
+        command
+    }
+}
+
+impl Drop for ScriptFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Arguments placed before the script file
+fn interpreter_args(interpreter: &Path) -> &'static [&'static str] {
+    match ShellKind::detect(interpreter) {
+        Some(ShellKind::PowerShell) => &["-NoProfile", "-File"],
+        Some(ShellKind::Cmd) => &["/C"],
+        _ => &[],
+    }
+}
+
+/// File extension of scripts run by an interpreter
+fn extension(interpreter: &Path) -> &'static str {
+    let name = interpreter
+        .file_stem()
+        .map(|name| name.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    match name.as_str() {
+        "python" | "python3" | "py" => "py",
+        "node" | "bun" => "js",
+        "deno" => "ts",
+        "pwsh" | "powershell" => "ps1",
+        "cmd" => "bat",
+        "ruby" => "rb",
+        "perl" => "pl",
+        "nu" => "nu",
+        "fish" => "fish",
+        _ => "sh",
+    }
+}
@@ -0,0 +1,333 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Placeholder in shell argument templates replaced with the command line
+const COMMAND_PLACEHOLDER: &str = "{command}";
+
+/// Shell configuration, either the name of a known shell or a custom program
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub enum ShellConfig {
+    /// Name or path of a shell, e.g. `bash` or `/usr/bin/fish`
+    Name(String),
+    /// Program with an argument template, `{command}` is replaced with the command line
+    Custom {
+        program: PathBuf,
+        #[serde(default)]
+        args: Vec<String>,
+    },
+}
+
+/// Shell families with their own invocation and quoting rules
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShellKind {
+    Posix,
+    Fish,
+    PowerShell,
+    Nu,
+    Cmd,
+}
+
+impl ShellKind {
+    /// Detect the shell family from the program name
+    pub fn detect(program: &Path) -> Option<ShellKind> {
+        let name = program.file_stem()?.to_string_lossy().to_lowercase();
+        match name.as_str() {
+            "sh" | "bash" | "zsh" | "dash" | "ksh" | "ash" => Some(ShellKind::Posix),
+            "fish" => Some(ShellKind::Fish),
+            "pwsh" | "powershell" => Some(ShellKind::PowerShell),
+            "nu" => Some(ShellKind::Nu),
+            "cmd" => Some(ShellKind::Cmd),
+            _ => None,
+        }
+    }
+
+    /// Default argument template to run a command line
+    fn default_args(self) -> Vec<String> {
+        let args: &[&str] = match self {
+            ShellKind::Posix | ShellKind::Fish | ShellKind::Nu => &["-c"],
+            ShellKind::PowerShell => &["-NoProfile", "-Command"],
+            ShellKind::Cmd => &["/C"],
+        };
+        args.iter()
+            .map(|arg| arg.to_string())
+            .chain([COMMAND_PLACEHOLDER.to_string()])
+            .collect()
+    }
+
+    /// Name of the shell family
+    pub fn name(self) -> &'static str {
+        match self {
+            ShellKind::Posix => "posix",
+            ShellKind::Fish => "fish",
+            ShellKind::PowerShell => "powershell",
+            ShellKind::Nu => "nu",
+            ShellKind::Cmd => "cmd",
+        }
+    }
+
+    /// Quote an argument for this shell
+    pub fn quote(self, arg: &str) -> String {
+        match self {
+            ShellKind::Posix => quote_posix(arg),
+            ShellKind::Fish => quote_fish(arg),
+            ShellKind::PowerShell => quote_powershell(arg),
+            ShellKind::Nu => quote_nu(arg),
+            ShellKind::Cmd => quote_cmd(arg),
+        }
+    }
+}
+
+/// Shell used to execute command lines
+#[derive(Debug, Clone)]
+pub struct Shell {
+    pub program: PathBuf,
+    pub args: Vec<String>,
+    pub kind: ShellKind,
+}
+
+impl Default for Shell {
+    fn default() -> Self {
+        if cfg!(target_os = "windows") {
+            Shell::from_kind(PathBuf::from("cmd"), ShellKind::Cmd)
+        } else {
+            Shell::from_kind(PathBuf::from("sh"), ShellKind::Posix)
+        }
+    }
+}
+
+impl Shell {
+    fn from_kind(program: PathBuf, kind: ShellKind) -> Self {
+        Self {
+            program,
+            args: kind.default_args(),
+            kind,
+        }
+    }
+
+    /// Resolve a shell configuration
+    ///
+    /// Unknown shells are assumed to follow POSIX rules.
+    pub fn from_config(config: &ShellConfig) -> Self {
+        match config {
+            ShellConfig::Name(name) => {
+                let program = PathBuf::from(name);
+                let kind = ShellKind::detect(&program).unwrap_or(ShellKind::Posix);
+                Shell::from_kind(program, kind)
+            }
+            ShellConfig::Custom { program, args } => {
+                let kind = ShellKind::detect(program).unwrap_or(ShellKind::Posix);
+                Self {
+                    program: program.clone(),
+                    args: if args.is_empty() {
+                        kind.default_args()
+                    } else {
+                        args.clone()
+                    },
+                    kind,
+                }
+            }
+        }
+    }
+
+    /// Quote an argument for this shell
+    pub fn quote(&self, arg: &str) -> String {
+        self.kind.quote(arg)
+    }
+
+    /// Join arguments into a command line for this shell
+    ///
+    /// PowerShell reads a quoted first token as a string, not a program, so the
+    /// line starts with the call operator `&` then.
+    pub fn join_args<S: AsRef<str>>(&self, args: &[S]) -> String {
+        let line = args
+            .iter()
+            .map(|arg| self.quote(arg.as_ref()))
+            .collect::<Vec<_>>()
+            .join(" ");
+        if self.kind == ShellKind::PowerShell && line.starts_with('\'') {
+            format!("& {}", line)
+        } else {
+            line
+        }
+    }
+
+    /// Create a process running the command line in this shell
+    pub fn command(&self, command_line: &str) -> Command {
+        let mut process = Command::new(&self.program);
+
+        let mut args: Vec<String> = self
+            .args
+            .iter()
+            .map(|arg| arg.replace(COMMAND_PLACEHOLDER, command_line))
+            .collect();
+        if !self
+            .args
+            .iter()
+            .any(|arg| arg.contains(COMMAND_PLACEHOLDER))
+        {
+            args.push(command_line.to_string());
+        }
+
+        for arg in args {
+            // cmd.exe parses its command line itself, default quoting would break it
+            #[cfg(windows)]
+            if self.kind == ShellKind::Cmd {
+                use std::os::windows::process::CommandExt;
+                process.raw_arg(arg);
+                continue;
+            }
+            process.arg(arg);
+        }
+
+        process
+    }
+}
+
+/// Characters that never need quoting in POSIX-like shells
+fn is_posix_safe(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "_-./:=@%+,".contains(c)
+}
+
+/// Quote an argument for POSIX shells
+pub fn quote_posix(arg: &str) -> String {
+    if !arg.is_empty() && arg.chars().all(is_posix_safe) {
+        return arg.to_string();
+    }
+
+    format!("'{}'", arg.replace('\'', r"'\''"))
+}
+
+/// Quote an argument for fish, where backslashes escape inside single quotes
+pub fn quote_fish(arg: &str) -> String {
+    if !arg.is_empty() && arg.chars().all(is_posix_safe) {
+        return arg.to_string();
+    }
+
+    format!("'{}'", arg.replace('\\', r"\\").replace('\'', r"\'"))
+}
+
+/// Quote an argument for PowerShell, where quotes are doubled inside single quotes
+pub fn quote_powershell(arg: &str) -> String {
+    let is_safe = |c: char| c.is_ascii_alphanumeric() || "_-./:\\".contains(c);
+
+    if !arg.is_empty() && arg.chars().all(is_safe) {
+        return arg.to_string();
+    }
+
+    format!("'{}'", arg.replace('\'', "''"))
+}
+
+/// Quote an argument for nushell
+///
+/// Single quoted strings are raw in nushell, arguments containing single
+/// quotes use double quotes with backslash escapes instead.
+pub fn quote_nu(arg: &str) -> String {
+    if !arg.is_empty() && arg.chars().all(is_posix_safe) {
+        return arg.to_string();
+    }
+
+    if !arg.contains('\'') {
+        return format!("'{}'", arg);
+    }
+
+    format!("\"{}\"", arg.replace('\\', r"\\").replace('"', "\\\""))
+}
+
+/// Quote an argument for cmd.exe following the Microsoft C runtime rules
+///
+/// cmd.exe expands `%VAR%` even inside double quotes, so each `%` is followed
+/// by `%cd:~,%`, an empty substring of the current directory that keeps the
+/// name from being read as a variable. `!` is kept as is: it is only expanded
+/// with delayed expansion (`cmd /V:ON`), which cannot be escaped reliably.
+pub fn quote_cmd(arg: &str) -> String {
+    let needs_quotes = |c: char| c.is_whitespace() || "\"&|<>^%!()".contains(c);
+
+    if !arg.is_empty() && !arg.chars().any(needs_quotes) {
+        return arg.to_string();
+    }
+
+    let mut quoted = String::from("\"");
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        match c {
+            '\\' => backslashes += 1,
+            '"' => {
+                // Backslashes before a quote must be escaped as well
+                quoted.push_str(&"\\".repeat(backslashes * 2 + 1));
+                quoted.push('"');
+                backslashes = 0;
+            }
+            '%' => {
+                quoted.push_str(&"\\".repeat(backslashes));
+                quoted.push_str("%%cd:~,%");
+                backslashes = 0;
+            }
+            _ => {
+                quoted.push_str(&"\\".repeat(backslashes));
+                quoted.push(c);
+                backslashes = 0;
+            }
+        }
+    }
+    // Trailing backslashes would escape the closing quote
+    quoted.push_str(&"\\".repeat(backslashes * 2));
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quote_posix_quotes_only_when_needed() {
+        assert_eq!(quote_posix("--port=8080"), "--port=8080");
+        assert_eq!(quote_posix(""), "''");
+        assert_eq!(quote_posix("a b"), "'a b'");
+        assert_eq!(quote_posix("it's"), r"'it'\''s'");
+        assert_eq!(quote_posix("$HOME"), "'$HOME'");
+    }
+
+    #[test]
+    fn quote_fish_escapes_backslashes_and_quotes() {
+        assert_eq!(quote_fish("plain"), "plain");
+        assert_eq!(quote_fish("it's"), r"'it\'s'");
+        assert_eq!(quote_fish(r"a\b c"), r"'a\\b c'");
+    }
+
+    #[test]
+    fn quote_cmd_follows_the_c_runtime_rules() {
+        assert_eq!(quote_cmd(r"C:\tools\x.exe"), r"C:\tools\x.exe");
+        assert_eq!(quote_cmd(""), "\"\"");
+        assert_eq!(quote_cmd("a b"), "\"a b\"");
+        assert_eq!(quote_cmd(r#"say "hi""#), r#""say \"hi\"""#);
+        assert_eq!(quote_cmd(r#"a\"b"#), r#""a\\\"b""#);
+        assert_eq!(quote_cmd(r"C:\my dir\"), r#""C:\my dir\\""#);
+    }
+
+    #[test]
+    fn quote_cmd_keeps_variables_from_expanding() {
+        assert_eq!(quote_cmd("%PATH%"), "\"%%cd:~,%PATH%%cd:~,%\"");
+        assert_eq!(quote_cmd("50%"), "\"50%%cd:~,%\"");
+    }
+
+    #[test]
+    fn join_args_uses_the_call_operator_for_quoted_powershell_programs() {
+        let pwsh = Shell::from_config(&ShellConfig::Name("pwsh".to_string()));
+        assert_eq!(
+            pwsh.join_args(&["/opt/my tools/x", "a"]),
+            "& '/opt/my tools/x' a"
+        );
+        assert_eq!(pwsh.join_args(&["git", "log"]), "git log");
+
+        let sh = Shell::from_config(&ShellConfig::Name("sh".to_string()));
+        assert_eq!(
+            sh.join_args(&["/opt/my tools/x", "a"]),
+            "'/opt/my tools/x' a"
+        );
+    }
+}
@@ -0,0 +1,237 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::{diagnostics, format, generator};
+use crate::{
+    get_config_dir, get_plugins_dir, get_simple_command_file, YkConfig, PLUGINS_DIR_NAME,
+    SIMPLE_COMMAND_FILE_STEM,
+};
+
+/// Directory holding the snippets of a project, found in a parent directory
+pub const PROJECT_DIR_NAME: &str = ".yk";
+/// Environment variable overriding the system directory
+const SYSTEM_DIR_ENV: &str = "YK_SYSTEM_DIR";
+/// Snippet file of a project, next to its plugins directory
+const PROJECT_COMMAND_FILE_STEM: &str = "commands";
+/// Plugin name of the project snippet file
+const PROJECT_PLUGIN_NAME: &str = "project";
+
+/// Where snippets come from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Scope {
+    /// `.yk` directory of the current project
+    Project,
+    /// Configuration directory of the user and `snippet_paths`
+    #[default]
+    User,
+    /// Shared by all users of the machine
+    System,
+}
+
+impl Scope {
+    /// Name shown in the picker and the preview
+    pub fn name(self) -> &'static str {
+        match self {
+            Scope::Project => "project",
+            Scope::User => "user",
+            Scope::System => "system",
+        }
+    }
+}
+
+/// Snippet file of a plugin, not loaded yet
+#[derive(Debug, Clone)]
+pub struct PluginFile {
+    pub name: String,
+    pub config_file: PathBuf,
+    pub scope: Scope,
+    /// Reported when the file is missing, the simple commands file is optional
+    pub required: bool,
+    /// `config_file` is an executable printing the snippets
+    pub generator: bool,
+    /// Directory of a plugin in a plugins directory, which may hold its manifest
+    pub plugin_dir: Option<PathBuf>,
+}
+
+impl PluginFile {
+    /// Name used in messages, e.g. `plugin ops` or `project plugin ops`
+    pub fn source_name(&self) -> String {
+        let kind = match self.name.as_str() {
+            "simple" => "simple commands".to_string(),
+            PROJECT_PLUGIN_NAME if self.scope == Scope::Project => "commands".to_string(),
+            name => format!("plugin {}", name),
+        };
+
+        match self.scope {
+            Scope::User => kind,
+            scope => format!("{} {}", scope.name(), kind),
+        }
+    }
+}
+
+/// Directory of snippets shared by all users, managed by administrators
+///
+/// `/etc/yk`, or `%PROGRAMDATA%\yk` on Windows, unless `YK_SYSTEM_DIR` is set.
+pub fn get_system_dir() -> PathBuf {
+    if let Some(dir) = env::var_os(SYSTEM_DIR_ENV).filter(|dir| !dir.is_empty()) {
+        return PathBuf::from(dir);
+    }
+
+    if cfg!(target_os = "windows") {
+        env::var_os("PROGRAMDATA")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(r"C:\ProgramData"))
+            .join("yk")
+    } else {
+        PathBuf::from("/etc/yk")
+    }
+}
+
+/// Snippet files of the system directory, which is optional
+///
+/// yk only reads them, they are never written.
+fn system_plugin_files() -> Vec<PluginFile> {
+    let system_dir = get_system_dir();
+
+    let mut plugin_files = Vec::new();
+    if let Some(config_file) = format::find_file(&system_dir, SIMPLE_COMMAND_FILE_STEM) {
+        plugin_files.push(PluginFile {
+            name: "simple".to_string(),
+            config_file,
+            scope: Scope::System,
+            required: true,
+            generator: false,
+            plugin_dir: None,
+        });
+    }
+    plugin_files.extend(plugin_files_in(
+        &system_dir.join(PLUGINS_DIR_NAME),
+        Scope::System,
+    ));
+    plugin_files
+}
+
+/// Nearest `.yk` directory in the current directory or one of its parents
+///
+/// The configuration directory itself is never a project directory.
+pub fn find_project_dir() -> Option<PathBuf> {
+    let current_dir = env::current_dir().ok()?;
+    let config_dir = get_config_dir();
+    current_dir
+        .ancestors()
+        .map(|dir| dir.join(PROJECT_DIR_NAME))
+        .find(|dir| dir.is_dir() && *dir != config_dir)
+}
+
+/// Project directory a snippet file belongs to
+pub fn project_dir_of(config_file: &Path) -> Option<PathBuf> {
+    config_file
+        .ancestors()
+        .find(|dir| dir.file_name().is_some_and(|name| name == PROJECT_DIR_NAME))
+        .map(Path::to_path_buf)
+}
+
+/// Snippet files of the plugins in a directory, one subdirectory per plugin
+///
+/// A plugin with a generator is loaded from its output, a snippet file next to
+/// it is ignored.
+pub fn plugin_files_in(plugins_dir: &Path, scope: Scope) -> Vec<PluginFile> {
+    let mut plugin_files: Vec<PluginFile> = fs::read_dir(plugins_dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter(|entry| entry.file_type().map(|ft| ft.is_dir()).unwrap_or(false))
+                .map(|entry| {
+                    let name = entry.file_name().to_string_lossy().to_string();
+                    let generator = generator::find_generator(&entry.path());
+                    PluginFile {
+                        plugin_dir: Some(entry.path()),
+                        generator: generator.is_some(),
+                        config_file: generator
+                            .unwrap_or_else(|| format::resolve_file(&entry.path(), &name)),
+                        name,
+                        scope,
+                        required: true,
+                    }
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    plugin_files.sort_by(|a, b| a.name.cmp(&b.name));
+    plugin_files
+}
+
+/// Snippet files listed in `snippet_paths`
+///
+/// Files are loaded as a plugin named after the file, directories like the
+/// plugins directory. Relative paths are resolved against the configuration
+/// directory.
+fn configured_plugin_files(config: &YkConfig) -> Vec<PluginFile> {
+    let mut plugin_files = Vec::new();
+
+    for path in &config.snippet_paths {
+        let path = get_config_dir().join(path);
+        if path.is_dir() {
+            plugin_files.extend(plugin_files_in(&path, Scope::User));
+        } else if path.is_file() {
+            let name = path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+                .unwrap_or_default();
+            plugin_files.push(PluginFile {
+                name,
+                config_file: path,
+                scope: Scope::User,
+                required: true,
+                generator: false,
+                plugin_dir: None,
+            });
+        } else {
+            diagnostics::warn(format!("Snippet path {:?} does not exist", path));
+        }
+    }
+
+    plugin_files
+}
+
+/// Snippet files of the current project
+pub fn project_plugin_files() -> Vec<PluginFile> {
+    let Some(project_dir) = find_project_dir() else {
+        return Vec::new();
+    };
+
+    let mut plugin_files = Vec::new();
+    if let Some(config_file) = format::find_file(&project_dir, PROJECT_COMMAND_FILE_STEM) {
+        plugin_files.push(PluginFile {
+            name: PROJECT_PLUGIN_NAME.to_string(),
+            config_file,
+            scope: Scope::Project,
+            required: true,
+            generator: false,
+            plugin_dir: None,
+        });
+    }
+    plugin_files.extend(plugin_files_in(
+        &project_dir.join(PLUGINS_DIR_NAME),
+        Scope::Project,
+    ));
+    plugin_files
+}
+
+/// All snippet files to load, from the system layer up to the project
+pub fn plugin_files(config: &YkConfig) -> Vec<PluginFile> {
+    let mut plugin_files = system_plugin_files();
+    plugin_files.push(PluginFile {
+        name: "simple".to_string(),
+        config_file: get_simple_command_file(),
+        scope: Scope::User,
+        required: false,
+        generator: false,
+        plugin_dir: None,
+    });
+    plugin_files.extend(plugin_files_in(&get_plugins_dir(), Scope::User));
+    plugin_files.extend(configured_plugin_files(config));
+    plugin_files.extend(project_plugin_files());
+    plugin_files
+}
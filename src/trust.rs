@@ -0,0 +1,240 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+use crate::error::{Result, YkError};
+use crate::sources::{self, Scope};
+use crate::{get_trust_file, FullCommandSnippet};
+
+/// Files of a directory and its subdirectories, sorted
+///
+/// Symbolic links are followed, so a linked file is fingerprinted with the
+/// contents of its target, wherever that is. `visited` holds the directories
+/// seen so far and stops link cycles. Dangling links are kept as files.
+fn collect_files(
+    dir: &Path,
+    visited: &mut Vec<PathBuf>,
+    files: &mut Vec<PathBuf>,
+) -> io::Result<()> {
+    let canonical = fs::canonicalize(dir)?;
+    if visited.contains(&canonical) {
+        return Ok(());
+    }
+    visited.push(canonical);
+
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        match fs::metadata(&path) {
+            Ok(metadata) if metadata.is_dir() => collect_files(&path, visited, files)?,
+            Ok(metadata) if metadata.is_file() => files.push(path),
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => files.push(path),
+            Err(e) => return Err(e),
+        }
+    }
+    files.sort();
+    Ok(())
+}
+
+/// Contents of a file, or the target of a dangling link
+fn file_contents(path: &Path) -> io::Result<Vec<u8>> {
+    match fs::read(path) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            Ok(fs::read_link(path)?.to_string_lossy().as_bytes().to_vec())
+        }
+        result => result,
+    }
+}
+
+/// SHA-256 fingerprint of the names and contents of all files in a project directory
+///
+/// Any change to a snippet file or script invalidates the approval. A
+/// cryptographic hash is used so that a repository cannot be changed to
+/// content with the approved fingerprint.
+pub fn fingerprint(project_dir: &Path) -> io::Result<String> {
+    let mut files = Vec::new();
+    collect_files(project_dir, &mut Vec::new(), &mut files)?;
+
+    let mut hasher = Sha256::new();
+    for file in files {
+        let relative = file.strip_prefix(project_dir).unwrap_or(&file);
+        // Lengths keep the boundaries between names and contents unambiguous
+        for bytes in [
+            relative.to_string_lossy().as_bytes(),
+            &file_contents(&file)?,
+        ] {
+            hasher.update((bytes.len() as u64).to_le_bytes());
+            hasher.update(bytes);
+        }
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+/// Approved project directories with the fingerprint they were approved with
+fn load_store(path: &Path) -> Result<BTreeMap<PathBuf, String>> {
+    match fs::read_to_string(path) {
+        Ok(content) => serde_json::from_str(&content).map_err(|e| {
+            YkError::Config(format!("Failed to read trusted projects {:?}: {}", path, e))
+        }),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn save_store(path: &Path, store: &BTreeMap<PathBuf, String>) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let serialized = serde_json::to_string_pretty(store).map_err(io::Error::from)?;
+    fs::write(path, serialized)?;
+    Ok(())
+}
+
+/// Whether a project directory is approved in its current state
+pub fn is_trusted(project_dir: &Path) -> Result<bool> {
+    let store = load_store(&get_trust_file())?;
+    Ok(store
+        .get(project_dir)
+        .is_some_and(|approved| *approved == fingerprint(project_dir).unwrap_or_default()))
+}
+
+/// Approve a project directory in its current state
+pub fn trust(project_dir: &Path) -> Result<()> {
+    let trust_file = get_trust_file();
+    let mut store = load_store(&trust_file)?;
+    store.insert(project_dir.to_path_buf(), fingerprint(project_dir)?);
+    save_store(&trust_file, &store)
+}
+
+/// Withdraw the approval of a project directory, returns whether it was approved
+pub fn revoke(project_dir: &Path) -> Result<bool> {
+    let trust_file = get_trust_file();
+    let mut store = load_store(&trust_file)?;
+    let removed = store.remove(project_dir).is_some();
+    if removed {
+        save_store(&trust_file, &store)?;
+    }
+    Ok(removed)
+}
+
+/// Project directories of a command and of the steps it runs
+fn project_dirs(command: &FullCommandSnippet) -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = std::iter::once(command)
+        .chain(command.steps.iter().map(|step| &step.command))
+        .filter(|command| command.scope == Scope::Project)
+        .filter_map(|command| sources::project_dir_of(&command.config_file))
+        .collect();
+    dirs.sort();
+    dirs.dedup();
+    dirs
+}
+
+/// Make sure project snippets are approved before anything of them runs
+///
+/// Unapproved projects are offered for approval when yk runs in a terminal,
+/// otherwise an error asks for `yk trust`.
+pub fn ensure_trusted(command: &FullCommandSnippet) -> Result<()> {
+    for project_dir in project_dirs(command) {
+        if is_trusted(&project_dir)? {
+            continue;
+        }
+
+        let message = format!(
+            "Project snippets in {:?} are not trusted or changed since they were trusted",
+            project_dir
+        );
+        if !io::stdin().is_terminal() {
+            return Err(YkError::Config(format!(
+                "{}, run `yk trust` in the project to allow them",
+                message
+            )));
+        }
+
+        print!("{}. Trust them? (y/N): ", message);
+        io::stdout().flush()?;
+        let mut input = String::new();
+        io::stdin().read_line(&mut input)?;
+        if input.trim().to_lowercase() != "y" {
+            return Err(YkError::Config(format!(
+                "Not running untrusted snippets of {:?}",
+                project_dir
+            )));
+        }
+        trust(&project_dir)?;
+    }
+    Ok(())
+}
+
+/// Approve or revoke the snippets of the current project
+pub fn trust_command(revoke_approval: bool) -> Result<()> {
+    let project_dir = sources::find_project_dir().ok_or_else(|| {
+        YkError::Config(format!(
+            "No {} directory found in the current directory or its parents",
+            sources::PROJECT_DIR_NAME
+        ))
+    })?;
+
+    if revoke_approval {
+        if revoke(&project_dir)? {
+            println!("Revoked trust in {:?}", project_dir);
+        } else {
+            println!("{:?} was not trusted", project_dir);
+        }
+    } else {
+        trust(&project_dir)?;
+        println!("Trusted {:?}", project_dir);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fingerprint_changes_with_names_and_contents() {
+        let temp = tempfile::tempdir().unwrap();
+        let project_dir = temp.path().join(".yk");
+        fs::create_dir_all(project_dir.join("plugins").join("db")).unwrap();
+        fs::write(project_dir.join("commands.json"), "{}").unwrap();
+        fs::write(project_dir.join("plugins").join("db").join("db.json"), "{}").unwrap();
+
+        let approved = fingerprint(&project_dir).unwrap();
+        assert_eq!(approved.len(), 64);
+        assert_eq!(fingerprint(&project_dir).unwrap(), approved);
+
+        fs::write(project_dir.join("commands.json"), "{ }").unwrap();
+        let changed = fingerprint(&project_dir).unwrap();
+        assert_ne!(changed, approved);
+
+        fs::rename(
+            project_dir.join("commands.json"),
+            project_dir.join("commands.yaml"),
+        )
+        .unwrap();
+        assert_ne!(fingerprint(&project_dir).unwrap(), changed);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn fingerprint_follows_symlinks() {
+        let temp = tempfile::tempdir().unwrap();
+        let project_dir = temp.path().join(".yk");
+        fs::create_dir_all(&project_dir).unwrap();
+        fs::write(temp.path().join("real.json"), "good").unwrap();
+        std::os::unix::fs::symlink("../real.json", project_dir.join("commands.json")).unwrap();
+        // A link back to a parent must not recurse forever
+        std::os::unix::fs::symlink("..", project_dir.join("parent")).unwrap();
+
+        let approved = fingerprint(&project_dir).unwrap();
+        fs::write(temp.path().join("real.json"), "EVIL").unwrap();
+        assert_ne!(fingerprint(&project_dir).unwrap(), approved);
+    }
+}
@@ -0,0 +1,414 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::process::ExitStatus;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::diagnostics;
+use crate::error::YkError;
+use crate::{
+    execute_command, find_command_by_name, not_started_status, qualified_id, AllCommandSnippets,
+    FullCommandSnippet,
+};
+
+/// What to do when a workflow step fails
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum FailurePolicy {
+    /// Stop the workflow
+    #[default]
+    Stop,
+    /// Continue with the next step
+    Continue,
+    /// Ask whether to continue
+    Prompt,
+}
+
+impl FailurePolicy {
+    /// Name as written in the configuration file
+    pub fn name(self) -> &'static str {
+        match self {
+            FailurePolicy::Stop => "stop",
+            FailurePolicy::Continue => "continue",
+            FailurePolicy::Prompt => "prompt",
+        }
+    }
+}
+
+/// Step of a workflow as written in the configuration file
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct WorkflowStep {
+    /// Name of another command, `plugin:name` to pick the command of a plugin
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snippet: Option<String>,
+    /// Inline command line executed in shell
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    /// Name shown while running, defaults to the command name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Ask before running this step
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub confirm: bool,
+    /// Overrides the `on_failure` policy of the workflow for this step
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_failure: Option<FailurePolicy>,
+}
+
+/// Workflow step resolved to the command it runs
+#[derive(Debug, Clone)]
+pub struct ResolvedStep {
+    pub command: FullCommandSnippet,
+    pub confirm: bool,
+    pub on_failure: FailurePolicy,
+}
+
+/// Resolve the steps of a workflow into commands
+///
+/// Steps referencing other workflows are expanded in place. `stack` holds
+/// the workflows being expanded to detect cycles.
+fn expand(
+    commands: &AllCommandSnippets,
+    workflow: &FullCommandSnippet,
+    stack: &mut Vec<String>,
+) -> Result<Vec<ResolvedStep>, String> {
+    let id = workflow.id.clone();
+    if stack.contains(&id) {
+        return Err(format!("workflow cycle: {} -> {}", stack.join(" -> "), id));
+    }
+    stack.push(id);
+
+    let mut resolved = Vec::new();
+    for (position, step) in workflow.workflow.iter().enumerate() {
+        let on_failure = step.on_failure.unwrap_or(workflow.on_failure);
+
+        match (&step.snippet, &step.command) {
+            (Some(reference), None) => {
+                // Commands of the same plugin take precedence
+                let target =
+                    find_command_by_name(commands, &qualified_id(&workflow.plugin_name, reference))
+                        .or_else(|_| find_command_by_name(commands, reference))
+                        .map_err(|e| format!("step {}: {}", position + 1, e))?;
+
+                if target.is_workflow() {
+                    let mut nested = expand(commands, target, stack)?;
+                    // Settings of the referencing step apply to the nested workflow as a whole
+                    if let Some(first) = nested.first_mut() {
+                        first.confirm |= step.confirm;
+                    }
+                    if let Some(policy) = step.on_failure {
+                        for nested_step in &mut nested {
+                            nested_step.on_failure = policy;
+                        }
+                    }
+                    resolved.extend(nested);
+                } else {
+                    let mut command = target.clone();
+                    if let Some(ref name) = step.name {
+                        command.name = name.clone();
+                    }
+                    resolved.push(ResolvedStep {
+                        command,
+                        confirm: step.confirm,
+                        on_failure,
+                    });
+                }
+            }
+            (None, Some(command_line)) => {
+                // Inline steps run in the shell and environment of the workflow
+                let mut command = workflow.clone();
+                command.name = step
+                    .name
+                    .clone()
+                    .unwrap_or_else(|| format!("{} step {}", workflow.name, position + 1));
+                command.executable = None;
+                command.entry_point = None;
+                command.script = None;
+                command.interpreter = None;
+                command.args = vec![command_line.clone()];
+                command.if_shell = true;
+                command.workflow = Vec::new();
+                command.steps = Vec::new();
+                command.refresh_complete_command();
+                resolved.push(ResolvedStep {
+                    command,
+                    confirm: step.confirm,
+                    on_failure,
+                });
+            }
+            _ => {
+                return Err(format!(
+                    "step {}: exactly one of 'snippet' and 'command' is required",
+                    position + 1
+                ))
+            }
+        }
+    }
+
+    stack.pop();
+    Ok(resolved)
+}
+
+/// Resolve the steps of all workflows
+///
+/// Workflows that cannot be resolved are removed with a warning.
+pub fn resolve_workflows(commands: &mut AllCommandSnippets) {
+    let resolve = |list: &[FullCommandSnippet]| -> Vec<_> {
+        list.iter()
+            .filter(|command| command.is_workflow())
+            .map(|command| expand(commands, command, &mut Vec::new()))
+            .collect()
+    };
+    let resolved = resolve(&commands.commands);
+    let resolved_shadowed = resolve(&commands.shadowed);
+
+    set_steps(&mut commands.commands, resolved);
+    set_steps(&mut commands.shadowed, resolved_shadowed);
+}
+
+/// Store resolved steps in the workflows of a list, in order
+fn set_steps(
+    commands: &mut Vec<FullCommandSnippet>,
+    resolved_steps: Vec<Result<Vec<ResolvedStep>, String>>,
+) {
+    let mut results = resolved_steps.into_iter();
+    commands.retain_mut(|command| {
+        if !command.is_workflow() {
+            return true;
+        }
+
+        match results.next() {
+            Some(Ok(steps)) => {
+                // Declarations of the workflow take precedence over those of its steps
+                for step in &steps {
+                    for (name, spec) in &step.command.placeholders {
+                        command
+                            .placeholders
+                            .entry(name.clone())
+                            .or_insert_with(|| spec.clone());
+                    }
+                }
+                command.steps = steps;
+                command.refresh_complete_command();
+                true
+            }
+            Some(Err(e)) => {
+                diagnostics::warn(format!("workflow '{}' is invalid: {}", command.id, e));
+                false
+            }
+            None => false,
+        }
+    });
+}
+
+/// Ask a yes/no question, `default` is used for empty input
+fn confirm(question: &str, default: bool) -> io::Result<bool> {
+    print!("{} ({}): ", question, if default { "Y/n" } else { "y/N" });
+    io::stdout().flush()?;
+
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
+    Ok(match input.trim().to_lowercase().as_str() {
+        "" => default,
+        answer => answer == "y",
+    })
+}
+
+/// Execute the steps of a workflow in order
+///
+/// Returns the status of the first failed step, or of the last step if all
+/// succeeded.
+pub fn execute_workflow(workflow: &FullCommandSnippet) -> Result<Option<ExitStatus>, YkError> {
+    let total = workflow.steps.len();
+    let mut first_failure = None;
+    let mut last_status = None;
+
+    for (position, step) in workflow.steps.iter().enumerate() {
+        println!(
+            "==> [{}/{}] {}: {}",
+            position + 1,
+            total,
+            step.command.name,
+            step.command.complete_command
+        );
+
+        if step.confirm && !confirm("Run this step?", true)? {
+            println!("Workflow cancelled");
+            break;
+        }
+
+        // A step that cannot be started, e.g. a missing executable, fails like any other
+        let status = match execute_command(&step.command) {
+            Ok(Some(status)) => status,
+            Ok(None) => continue,
+            Err(e) => {
+                diagnostics::error(e);
+                not_started_status()
+            }
+        };
+        last_status = Some(status);
+        if status.success() {
+            continue;
+        }
+
+        first_failure.get_or_insert(status);
+        let code = status
+            .code()
+            .map(|code| code.to_string())
+            .unwrap_or_else(|| "signal".to_string());
+        let keep_going = match step.on_failure {
+            FailurePolicy::Stop => false,
+            FailurePolicy::Continue => true,
+            FailurePolicy::Prompt => {
+                confirm(&format!("Step failed with {}, continue?", code), false)?
+            }
+        };
+        if !keep_going {
+            println!(
+                "Workflow stopped: step {} failed with {}",
+                position + 1,
+                code
+            );
+            break;
+        }
+    }
+
+    Ok(first_failure.or(last_status))
+}
+
+/// Fill placeholders of every step with shared values
+pub fn fill_steps(steps: &[ResolvedStep], values: &HashMap<String, String>) -> Vec<ResolvedStep> {
+    steps
+        .iter()
+        .map(|step| ResolvedStep {
+            command: step.command.fill_placeholders(values),
+            ..step.clone()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(name: &str) -> FullCommandSnippet {
+        FullCommandSnippet {
+            id: qualified_id("ops", name),
+            name: name.to_string(),
+            plugin_name: "ops".to_string(),
+            args: vec![name.to_string()],
+            ..Default::default()
+        }
+    }
+
+    fn workflow(name: &str, steps: Vec<WorkflowStep>) -> FullCommandSnippet {
+        FullCommandSnippet {
+            workflow: steps,
+            ..command(name)
+        }
+    }
+
+    fn snippet_step(reference: &str) -> WorkflowStep {
+        WorkflowStep {
+            snippet: Some(reference.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn all(commands: Vec<FullCommandSnippet>) -> AllCommandSnippets {
+        AllCommandSnippets {
+            commands,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn expand_flattens_nested_workflows() {
+        let ci = workflow("ci", vec![snippet_step("build"), snippet_step("test")]);
+        let release = workflow(
+            "release",
+            vec![
+                WorkflowStep {
+                    confirm: true,
+                    on_failure: Some(FailurePolicy::Continue),
+                    ..snippet_step("ci")
+                },
+                WorkflowStep {
+                    command: Some("echo done".to_string()),
+                    ..Default::default()
+                },
+            ],
+        );
+        let commands = all(vec![command("build"), command("test"), ci, release.clone()]);
+
+        let steps = expand(&commands, &release, &mut Vec::new()).unwrap();
+        let summary: Vec<_> = steps
+            .iter()
+            .map(|step| {
+                (
+                    step.command.name.as_str(),
+                    step.command.args.join(" "),
+                    step.confirm,
+                    step.on_failure,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("build", "build".to_string(), true, FailurePolicy::Continue),
+                ("test", "test".to_string(), false, FailurePolicy::Continue),
+                (
+                    "release step 2",
+                    "echo done".to_string(),
+                    false,
+                    FailurePolicy::Stop
+                ),
+            ]
+        );
+        assert!(steps[2].command.if_shell);
+    }
+
+    #[test]
+    fn expand_detects_cycles() {
+        let a = workflow("a", vec![snippet_step("b")]);
+        let b = workflow("b", vec![snippet_step("a")]);
+        let commands = all(vec![a.clone(), b]);
+
+        assert_eq!(
+            expand(&commands, &a, &mut Vec::new()).unwrap_err(),
+            format!(
+                "workflow cycle: {} -> {} -> {}",
+                qualified_id("ops", "a"),
+                qualified_id("ops", "b"),
+                qualified_id("ops", "a")
+            )
+        );
+    }
+
+    #[test]
+    fn resolve_workflows_removes_invalid_workflows() {
+        let mut commands = all(vec![
+            command("build"),
+            workflow("ok", vec![snippet_step("build")]),
+            workflow("missing", vec![snippet_step("nothing")]),
+            workflow(
+                "both",
+                vec![WorkflowStep {
+                    command: Some("true".to_string()),
+                    ..snippet_step("build")
+                }],
+            ),
+        ]);
+
+        resolve_workflows(&mut commands);
+        let names: Vec<_> = commands
+            .commands
+            .iter()
+            .map(|command| command.name.as_str())
+            .collect();
+        assert_eq!(names, ["build", "ok"]);
+        assert_eq!(commands.commands[1].steps.len(), 1);
+    }
+}